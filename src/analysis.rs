@@ -1,128 +1,401 @@
+//! The analyses a specification is solved as: evaluation of set
+//! expressions, constants and the universe, on the monotone framework.
+
 use std::collections::{HashMap, HashSet};
 
-use crate::parser;
+use crate::ast::{self, Element, Expr};
+use crate::diagnostic::Diagnostic;
+use crate::framework::{Config, Confluence, Direction, Equation, Framework, PowerSet};
+use crate::names::{self, natural_cmp};
 
-pub fn reaching_definitions(node: &parser::Node) -> usize {
-    let mut iteration = 1;
+pub use crate::framework::Solution;
 
-    let mut data_points_a = HashMap::<String, HashSet<String>>::new();
-    let mut data_points_b = HashMap::<String, HashSet<String>>::new();
-    find_data_points(&mut data_points_a, node);
-    find_data_points(&mut data_points_b, node);
+/// The definitions in every data point.
+pub type DataPoints = HashMap<String, HashSet<String>>;
 
-    loop {
-        for data_flow_equation in node.children.iter() {
-            let l = &data_flow_equation.children[0];
-            let name = (&l.token).as_ref().unwrap().lexeme.clone();
-            let r = &data_flow_equation.children[1];
-            let points = solve_points(&mut data_points_a, r);
-            data_points_a.insert(name, points);
-        }
+/// Values of the `let` bindings in a specification.
+pub type Constants = HashMap<String, HashSet<String>>;
+
+// What names in an expression refer to, and what `~` complements against.
+struct Environment<'a> {
+    data_points: &'a DataPoints,
+    constants: &'a Constants,
+    universe: &'a HashSet<String>,
+}
+
+/// The analyses a specification can be solved as, all over the
+/// [`PowerSet`] of its definitions. The equations are the same; the analysis
+/// decides where data points start and which end of the equations a round
+/// starts from. The direction only changes how fast the fixpoint is reached.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Analysis {
+    /// A forward may analysis.
+    ReachingDefinitions,
+    /// A backward may analysis.
+    LiveVariables,
+    /// A forward must analysis. Data points start from the declared
+    /// universe, or from every definition written in the specification if
+    /// there is none.
+    AvailableExpressions,
+}
 
-        println!("Iteration {}", iteration);
-        print_datapoints(&data_points_a);
+impl Analysis {
+    /// The name `lattis solve -a` takes.
+    pub fn name(self) -> &'static str {
+        match self {
+            Analysis::ReachingDefinitions => "reaching-definitions",
+            Analysis::LiveVariables => "live-variables",
+            Analysis::AvailableExpressions => "available-expressions",
+        }
+    }
 
-        if !has_changed(&data_points_a, &data_points_b) {
-            break;
+    /// Which end of the equations a round starts from.
+    pub fn direction(self) -> Direction {
+        match self {
+            Analysis::LiveVariables => Direction::Backward,
+            _ => Direction::Forward,
         }
+    }
 
-        data_points_b = data_points_a.clone();
-        iteration += 1;
+    /// Whether data points start empty or full.
+    pub fn confluence(self) -> Confluence {
+        match self {
+            Analysis::AvailableExpressions => Confluence::Must,
+            _ => Confluence::May,
+        }
     }
+}
+
+/// Solves `spec` as [`Analysis::ReachingDefinitions`].
+pub fn reaching_definitions(spec: &ast::Spec) -> Result<Solution, Diagnostic> {
+    solve(spec, Analysis::ReachingDefinitions, Config::default())
+}
+
+/// Solves `spec` as [`Analysis::LiveVariables`].
+pub fn live_variables(spec: &ast::Spec) -> Result<Solution, Diagnostic> {
+    solve(spec, Analysis::LiveVariables, Config::default())
+}
 
-    return iteration;
+/// Solves `spec` as [`Analysis::AvailableExpressions`].
+pub fn available_expressions(spec: &ast::Spec) -> Result<Solution, Diagnostic> {
+    solve(spec, Analysis::AvailableExpressions, Config::default())
 }
 
-fn solve_points(
-    data_points: &mut HashMap<String, HashSet<String>>,
-    node: &parser::Node,
-) -> HashSet<String> {
-    let mut points = HashSet::new();
-
-    // Copy other data points
-    if node.node_type == parser::NodeType::DataPoint {
-        let token = node.token.as_ref().unwrap();
-        let name = token.lexeme.clone();
-        let other_points = data_points.get(&name).unwrap();
-        points.extend(other_points.iter().cloned());
-    } else if node.node_type == parser::NodeType::Definition {
-        let token = node.token.as_ref().unwrap();
-        let name = token.lexeme.clone();
-        points.insert(name);
-    } else if node.node_type == parser::NodeType::SetDifference {
-        let left = &node.children[0];
-        let right = &node.children[1];
-        let left_points = solve_points(data_points, left);
-        let right_points = solve_points(data_points, right);
-        points.extend(left_points.iter().cloned());
-        points.retain(|x| !right_points.contains(x));
-    } else if node.node_type == parser::NodeType::Union {
-        let left = &node.children[0];
-        let right = &node.children[1];
-        let left_points = solve_points(data_points, left);
-        let right_points = solve_points(data_points, right);
-        points.extend(left_points.iter().cloned());
-        points.extend(right_points.iter().cloned());
+/// Solves the equations of `spec` as transfer functions over sets of
+/// definitions. Fails if they never settle, which takes an equation that
+/// removes or complements a data point it depends on.
+pub fn solve(spec: &ast::Spec, analysis: Analysis, config: Config) -> Result<Solution, Diagnostic> {
+    let (universe, _) = evaluate_universe(spec);
+    let (constants, _) = evaluate_constants(spec);
+    let top = if spec.universes.is_empty() {
+        find_definitions(spec)
     } else {
-        for child in &node.children {
-            let child_points = solve_points(data_points, child);
-            points.extend(child_points.iter().cloned());
+        universe.clone()
+    };
+    let framework = Framework::new(
+        PowerSet::new(top),
+        analysis.direction(),
+        analysis.confluence(),
+    )
+    .with_config(config);
+
+    let equations = spec
+        .equations
+        .iter()
+        .map(|equation| {
+            let reads = equation
+                .rhs
+                .vars()
+                .into_iter()
+                .map(|var| var.name.clone())
+                .collect();
+            Equation::new(
+                equation.lhs.name.clone(),
+                reads,
+                |data_points: &DataPoints| {
+                    let environment = Environment {
+                        data_points,
+                        constants: &constants,
+                        universe: &universe,
+                    };
+                    solve_points(&environment, &equation.rhs)
+                },
+            )
+        })
+        .collect::<Vec<_>>();
+    framework.solve(&equations).map_err(|diagnostic| {
+        diagnostic.with_help(
+            "`/` or `~` of a data point in the same cycle can keep the equations \
+             from settling",
+        )
+    })
+}
+
+/// Evaluates the `universe` declaration. The universe is empty when none is
+/// declared, which is an error as soon as `~` is used.
+pub fn evaluate_universe(spec: &ast::Spec) -> (HashSet<String>, Vec<Diagnostic>) {
+    let mut diagnostics = Vec::new();
+    let mut universe = None;
+    for declaration in &spec.universes {
+        if universe.is_some() {
+            diagnostics.push(
+                Diagnostic::error("the universe is declared twice")
+                    .with_label(declaration.keyword, "redeclared here"),
+            );
+            continue;
         }
+
+        let mut not_literal = None;
+        declaration.value.walk(&mut |expr| {
+            if matches!(expr, Expr::Var(_) | Expr::Complement { .. }) {
+                not_literal.get_or_insert(expr.span());
+            }
+        });
+        if let Some(span) = not_literal {
+            diagnostics.push(
+                Diagnostic::error("the universe must be written with set literals")
+                    .with_label(span, "not a set literal"),
+            );
+            universe = Some(HashSet::new());
+            continue;
+        }
+
+        let environment = Environment {
+            data_points: &DataPoints::new(),
+            constants: &Constants::new(),
+            universe: &HashSet::new(),
+        };
+        universe = Some(solve_points(&environment, &declaration.value));
     }
 
-    return points;
+    if universe.is_none() {
+        let expressions = spec
+            .bindings
+            .iter()
+            .map(|binding| &binding.value)
+            .chain(spec.equations.iter().map(|equation| &equation.rhs));
+        for expression in expressions {
+            expression.walk(&mut |expr| {
+                if let Expr::Complement { op, .. } = expr {
+                    diagnostics.push(
+                        Diagnostic::error("`~` needs a declared universe")
+                            .with_label(*op, "complement of what?")
+                            .with_help("declare the universe, e.g. `universe {d1..d12};`"),
+                    );
+                }
+            });
+        }
+    }
+    (universe.unwrap_or_default(), diagnostics)
 }
 
-fn has_changed(a: &HashMap<String, HashSet<String>>, b: &HashMap<String, HashSet<String>>) -> bool {
-    for (key, value) in a {
-        let other_value = b.get(key).unwrap();
-        if value.difference(other_value).count() > 0 {
-            return true;
+/// Evaluates every `let` binding. Bindings that clash with a data point, refer
+/// to one, or are part of a cycle are reported and left out of the result.
+pub fn evaluate_constants(spec: &ast::Spec) -> (Constants, Vec<Diagnostic>) {
+    let data_point_names = spec
+        .equations
+        .iter()
+        .map(|equation| equation.lhs.name.as_str())
+        .collect::<HashSet<_>>();
+
+    let mut diagnostics = Vec::new();
+    let mut bindings = HashMap::new();
+    for binding in &spec.bindings {
+        let constant = &binding.name;
+        if data_point_names.contains(constant.name.as_str()) {
+            diagnostics.push(
+                Diagnostic::error(format!(
+                    "`{}` is defined both as a constant and as a data point",
+                    constant.name
+                ))
+                .with_label(constant.span, "constant defined here"),
+            );
+        } else if bindings.contains_key(constant.name.as_str()) {
+            diagnostics.push(
+                Diagnostic::error(format!("constant `{}` is defined twice", constant.name))
+                    .with_label(constant.span, "redefined here"),
+            );
+        } else {
+            bindings.insert(constant.name.as_str(), binding);
         }
     }
-    return false;
+
+    let (universe, _) = evaluate_universe(spec);
+    let mut evaluator = ConstantEvaluator {
+        universe,
+        bindings,
+        data_point_names,
+        in_progress: Vec::new(),
+        constants: Constants::new(),
+        failed: HashSet::new(),
+        diagnostics,
+    };
+    let mut names = evaluator.bindings.keys().copied().collect::<Vec<_>>();
+    names.sort_by(|a, b| natural_cmp(a, b));
+    for constant in names {
+        evaluator.evaluate(constant);
+    }
+    (evaluator.constants, evaluator.diagnostics)
+}
+
+struct ConstantEvaluator<'a> {
+    universe: HashSet<String>,
+    bindings: HashMap<&'a str, &'a ast::LetBinding>,
+    data_point_names: HashSet<&'a str>,
+    in_progress: Vec<&'a str>,
+    constants: Constants,
+    failed: HashSet<&'a str>,
+    diagnostics: Vec<Diagnostic>,
 }
 
-fn print_datapoints(data_points: &HashMap<String, HashSet<String>>) {
-    let mut keys = Vec::new();
-    for (key, _) in data_points {
-        keys.push(key.clone());
+impl<'a> ConstantEvaluator<'a> {
+    // Evaluates `constant` after the constants it refers to. Returns false if
+    // it could not be evaluated.
+    fn evaluate(&mut self, constant: &'a str) -> bool {
+        if self.constants.contains_key(constant) {
+            return true;
+        }
+        if self.failed.contains(constant) {
+            return false;
+        }
+
+        self.in_progress.push(constant);
+        let body = &self.bindings[constant].value;
+
+        let mut ok = true;
+        for reference in body.vars() {
+            let span = reference.span;
+            let referenced = reference.name.as_str();
+            if self.in_progress.contains(&referenced) {
+                self.diagnostics.push(
+                    Diagnostic::error(format!("constant `{}` depends on itself", referenced))
+                        .with_label(span, "cyclic reference"),
+                );
+                ok = false;
+            } else if self.bindings.contains_key(referenced) {
+                ok &= self.evaluate(referenced);
+            } else if self.data_point_names.contains(referenced) {
+                self.diagnostics.push(
+                    Diagnostic::error(format!(
+                        "constant `{}` refers to data point `{}`",
+                        constant, referenced
+                    ))
+                    .with_label(span, "data points are not allowed in constants"),
+                );
+                ok = false;
+            } else {
+                self.diagnostics.push(
+                    Diagnostic::error(format!(
+                        "cannot find `{}` in this specification",
+                        referenced
+                    ))
+                    .with_label(span, "not a constant"),
+                );
+                ok = false;
+            }
+        }
+        self.in_progress.pop();
+
+        if !ok {
+            self.failed.insert(constant);
+            return false;
+        }
+        let environment = Environment {
+            data_points: &DataPoints::new(),
+            constants: &self.constants,
+            universe: &self.universe,
+        };
+        let value = solve_points(&environment, body);
+        self.constants.insert(constant.to_string(), value);
+        true
     }
-    keys.sort_by(|a, b| {
-        let mut a = a.clone();
-        a.remove(0);
-        let mut b = b.clone();
-        b.remove(0);
-        let a = a.parse::<usize>().unwrap();
-        let b = b.parse::<usize>().unwrap();
-        a.cmp(&b)
-    });
-
-    for key in &keys {
-        print!("{}: {{", key);
-        let points = data_points.get(key).unwrap();
-        let mut points = points.iter().collect::<Vec<_>>();
-        points.sort_by(|a, b| {
-            let mut a = (*a).clone();
-            a.remove(0);
-            let mut b = (*b).clone();
-            b.remove(0);
-            let a = a.parse::<usize>().unwrap();
-            let b = b.parse::<usize>().unwrap();
-            a.cmp(&b)
-        });
+}
 
-        for point in points {
-            print!("{}, ", point);
+fn solve_points(environment: &Environment, expr: &Expr) -> HashSet<String> {
+    match expr {
+        // Copy other data points or constants
+        Expr::Var(ident) => environment
+            .data_points
+            .get(&ident.name)
+            .or_else(|| environment.constants.get(&ident.name))
+            .cloned()
+            .unwrap_or_default(),
+        Expr::SetLit(set) => {
+            let mut points = HashSet::new();
+            for element in &set.elements {
+                match element {
+                    Element::Definition(definition) => {
+                        points.insert(definition.name.clone());
+                    }
+                    Element::Range { start, end } => {
+                        points.extend(
+                            names::expand_range(&start.name, &end.name).unwrap_or_default(),
+                        );
+                    }
+                }
+            }
+            points
+        }
+        Expr::Complement { operand, .. } => {
+            let operand_points = solve_points(environment, operand);
+            environment
+                .universe
+                .difference(&operand_points)
+                .cloned()
+                .collect()
+        }
+        Expr::Diff { lhs, rhs, .. } => {
+            let right_points = solve_points(environment, rhs);
+            let mut points = solve_points(environment, lhs);
+            points.retain(|x| !right_points.contains(x));
+            points
+        }
+        Expr::Intersection { lhs, rhs, .. } => {
+            let left_points = solve_points(environment, lhs);
+            let right_points = solve_points(environment, rhs);
+            left_points.intersection(&right_points).cloned().collect()
+        }
+        Expr::Union { lhs, rhs, .. } => {
+            let mut points = solve_points(environment, lhs);
+            points.extend(solve_points(environment, rhs));
+            points
         }
-        println!("}}");
     }
 }
 
-fn find_data_points(data_points: &mut HashMap<String, HashSet<String>>, node: &parser::Node) {
-    for child in &node.children {
-        let l = &child.children[0];
-        let name = (&l.token).as_ref().unwrap().lexeme.clone();
-        data_points.insert(name, HashSet::new());
+/// Data point names with their definitions, both in natural order.
+pub fn sorted_datapoints(data_points: &DataPoints) -> Vec<(&String, Vec<&String>)> {
+    let mut keys = data_points.keys().collect::<Vec<_>>();
+    keys.sort_by(|a, b| natural_cmp(a, b));
+
+    keys.into_iter()
+        .map(|key| {
+            let mut points = data_points[key].iter().collect::<Vec<_>>();
+            points.sort_by(|a, b| natural_cmp(a, b));
+            (key, points)
+        })
+        .collect()
+}
+
+// Every definition written in a set literal of the specification.
+fn find_definitions(spec: &ast::Spec) -> HashSet<String> {
+    let environment = Environment {
+        data_points: &DataPoints::new(),
+        constants: &Constants::new(),
+        universe: &HashSet::new(),
+    };
+    let mut definitions = HashSet::new();
+    let expressions = spec
+        .bindings
+        .iter()
+        .map(|binding| &binding.value)
+        .chain(spec.equations.iter().map(|equation| &equation.rhs));
+    for expression in expressions {
+        expression.walk(&mut |expr| {
+            if let Expr::SetLit(_) = expr {
+                definitions.extend(solve_points(&environment, expr));
+            }
+        });
     }
+    definitions
 }
@@ -0,0 +1,407 @@
+//! A typed syntax tree for specifications.
+//!
+//! The parser builds a generic syntax tree; [`Spec::try_from`]
+//! turns a simplified tree into these types, where every equation has exactly
+//! one left-hand side and every operator exactly its operands.
+
+use std::fmt;
+
+use crate::parser::{self, NodeType};
+use crate::span::Span;
+
+/// A name together with where it was written.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Ident {
+    /// The name as written.
+    pub name: String,
+    /// Where it was written.
+    pub span: Span,
+}
+
+/// Every statement of a specification, by kind.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Spec {
+    /// The data flow equations, in the order they are solved.
+    pub equations: Vec<Equation>,
+    /// The `let` bindings, in the order they are written.
+    pub bindings: Vec<LetBinding>,
+    /// Every `universe` declaration. More than one is an error that is
+    /// reported during evaluation.
+    pub universes: Vec<Universe>,
+}
+
+/// `lhs = rhs`
+#[derive(Debug, PartialEq, Clone)]
+pub struct Equation {
+    /// The data point being defined.
+    pub lhs: Ident,
+    /// Its value.
+    pub rhs: Expr,
+}
+
+/// `let name = value`
+#[derive(Debug, PartialEq, Clone)]
+pub struct LetBinding {
+    /// The constant being defined.
+    pub name: Ident,
+    /// Its value.
+    pub value: Expr,
+}
+
+/// `universe value`
+#[derive(Debug, PartialEq, Clone)]
+pub struct Universe {
+    /// The `universe` keyword.
+    pub keyword: Span,
+    /// The declared universe.
+    pub value: Expr,
+}
+
+/// A set expression.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Expr {
+    /// A data point or a constant.
+    Var(Ident),
+    /// A set literal.
+    SetLit(SetLit),
+    /// `lhs U rhs`
+    Union {
+        /// The left operand.
+        lhs: Box<Expr>,
+        /// The right operand.
+        rhs: Box<Expr>,
+        /// The operator.
+        op: Span,
+    },
+    /// `lhs I rhs`
+    Intersection {
+        /// The left operand.
+        lhs: Box<Expr>,
+        /// The right operand.
+        rhs: Box<Expr>,
+        /// The operator.
+        op: Span,
+    },
+    /// `lhs / rhs`
+    Diff {
+        /// The left operand.
+        lhs: Box<Expr>,
+        /// The right operand.
+        rhs: Box<Expr>,
+        /// The operator.
+        op: Span,
+    },
+    /// `~operand`
+    Complement {
+        /// The set being complemented.
+        operand: Box<Expr>,
+        /// The `~`.
+        op: Span,
+    },
+}
+
+/// `{d1, d2..d5}` or `∅`. `span` points at the opening `{` or the `∅`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct SetLit {
+    /// The elements in the order they are written.
+    pub elements: Vec<Element>,
+    /// The opening `{` or the `∅`.
+    pub span: Span,
+}
+
+/// One element of a set literal.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Element {
+    /// A single definition.
+    Definition(Ident),
+    /// `start..end`; simplified trees have these expanded already.
+    Range {
+        /// The first definition of the range.
+        start: Ident,
+        /// The last definition of the range.
+        end: Ident,
+    },
+}
+
+impl Expr {
+    /// The span of the operator or name that makes up this expression.
+    pub fn span(&self) -> Span {
+        match self {
+            Expr::Var(ident) => ident.span,
+            Expr::SetLit(set) => set.span,
+            Expr::Union { op, .. }
+            | Expr::Intersection { op, .. }
+            | Expr::Diff { op, .. }
+            | Expr::Complement { op, .. } => *op,
+        }
+    }
+
+    /// The direct subexpressions, left to right.
+    pub fn operands(&self) -> Vec<&Expr> {
+        match self {
+            Expr::Var(_) | Expr::SetLit(_) => Vec::new(),
+            Expr::Union { lhs, rhs, .. }
+            | Expr::Intersection { lhs, rhs, .. }
+            | Expr::Diff { lhs, rhs, .. } => vec![lhs, rhs],
+            Expr::Complement { operand, .. } => vec![operand],
+        }
+    }
+
+    /// Every name this expression refers to, left to right.
+    pub fn vars(&self) -> Vec<&Ident> {
+        let mut vars = Vec::new();
+        self.walk(&mut |expr| {
+            if let Expr::Var(ident) = expr {
+                vars.push(ident);
+            }
+        });
+        vars
+    }
+
+    /// Calls `visit` on this expression and then on every subexpression.
+    pub fn walk<'a>(&'a self, visit: &mut impl FnMut(&'a Expr)) {
+        visit(self);
+        for operand in self.operands() {
+            operand.walk(visit);
+        }
+    }
+}
+
+/// A syntax tree node that does not have the shape the parser produces.
+#[derive(Debug, PartialEq, Clone)]
+pub struct InvalidTree {
+    /// The kind of node that was not expected.
+    pub node_type: NodeType,
+}
+
+impl fmt::Display for InvalidTree {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "unexpected {:?} node", self.node_type)
+    }
+}
+
+impl std::error::Error for InvalidTree {}
+
+fn invalid<T>(node: &parser::Node) -> Result<T, InvalidTree> {
+    Err(InvalidTree {
+        node_type: node.node_type.clone(),
+    })
+}
+
+fn ident(node: &parser::Node) -> Result<Ident, InvalidTree> {
+    match (&node.token, node.children.is_empty()) {
+        (Some(token), true) => Ok(Ident {
+            name: token.lexeme.clone(),
+            span: token.span,
+        }),
+        _ => invalid(node),
+    }
+}
+
+fn pair(node: &parser::Node) -> Result<(&parser::Node, &parser::Node), InvalidTree> {
+    match node.children.as_slice() {
+        [lhs, rhs] => Ok((lhs, rhs)),
+        _ => invalid(node),
+    }
+}
+
+fn operator(node: &parser::Node) -> Result<Span, InvalidTree> {
+    match &node.token {
+        Some(token) => Ok(token.span),
+        None => invalid(node),
+    }
+}
+
+impl TryFrom<&parser::Node> for Spec {
+    type Error = InvalidTree;
+
+    /// Converts a statement list, simplified or straight from the parser.
+    /// `include` directives must have been resolved.
+    fn try_from(node: &parser::Node) -> Result<Spec, InvalidTree> {
+        if node.node_type == NodeType::Root {
+            return match node.children.as_slice() {
+                [list] => Spec::try_from(list),
+                _ => invalid(node),
+            };
+        }
+        if node.node_type != NodeType::DataFlowEquationList {
+            return invalid(node);
+        }
+
+        let mut spec = Spec::default();
+        for statement in &node.children {
+            match statement.node_type {
+                NodeType::DataflowEquation => {
+                    let (lhs, rhs) = pair(statement)?;
+                    if lhs.node_type != NodeType::DataPoint {
+                        return invalid(lhs);
+                    }
+                    spec.equations.push(Equation {
+                        lhs: ident(lhs)?,
+                        rhs: Expr::try_from(rhs)?,
+                    });
+                }
+                NodeType::LetBinding => {
+                    let (name, value) = pair(statement)?;
+                    if name.node_type != NodeType::Constant {
+                        return invalid(name);
+                    }
+                    spec.bindings.push(LetBinding {
+                        name: ident(name)?,
+                        value: Expr::try_from(value)?,
+                    });
+                }
+                NodeType::Universe => match statement.children.as_slice() {
+                    [value] => spec.universes.push(Universe {
+                        keyword: operator(statement)?,
+                        value: Expr::try_from(value)?,
+                    }),
+                    _ => return invalid(statement),
+                },
+                _ => return invalid(statement),
+            }
+        }
+        Ok(spec)
+    }
+}
+
+impl TryFrom<&parser::Node> for Expr {
+    type Error = InvalidTree;
+
+    fn try_from(node: &parser::Node) -> Result<Expr, InvalidTree> {
+        let binary = |node: &parser::Node| -> Result<(Box<Expr>, Box<Expr>, Span), InvalidTree> {
+            let (lhs, rhs) = pair(node)?;
+            Ok((
+                Box::new(Expr::try_from(lhs)?),
+                Box::new(Expr::try_from(rhs)?),
+                operator(node)?,
+            ))
+        };
+
+        match node.node_type {
+            NodeType::Body => match node.children.as_slice() {
+                [expression] => Expr::try_from(expression),
+                _ => invalid(node),
+            },
+            NodeType::DataPoint => Ok(Expr::Var(ident(node)?)),
+            NodeType::Set => {
+                let elements = node
+                    .children
+                    .iter()
+                    .map(Element::try_from)
+                    .collect::<Result<_, _>>()?;
+                Ok(Expr::SetLit(SetLit {
+                    elements,
+                    span: operator(node)?,
+                }))
+            }
+            NodeType::Union => {
+                let (lhs, rhs, op) = binary(node)?;
+                Ok(Expr::Union { lhs, rhs, op })
+            }
+            NodeType::Intersection => {
+                let (lhs, rhs, op) = binary(node)?;
+                Ok(Expr::Intersection { lhs, rhs, op })
+            }
+            NodeType::SetDifference => {
+                let (lhs, rhs, op) = binary(node)?;
+                Ok(Expr::Diff { lhs, rhs, op })
+            }
+            NodeType::Complement => match node.children.as_slice() {
+                [operand] => Ok(Expr::Complement {
+                    operand: Box::new(Expr::try_from(operand)?),
+                    op: operator(node)?,
+                }),
+                _ => invalid(node),
+            },
+            _ => invalid(node),
+        }
+    }
+}
+
+impl TryFrom<&parser::Node> for Element {
+    type Error = InvalidTree;
+
+    fn try_from(node: &parser::Node) -> Result<Element, InvalidTree> {
+        match node.node_type {
+            NodeType::Definition => Ok(Element::Definition(ident(node)?)),
+            NodeType::Range => {
+                let (start, end) = pair(node)?;
+                Ok(Element::Range {
+                    start: ident(start)?,
+                    end: ident(end)?,
+                })
+            }
+            _ => invalid(node),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    fn parse(text: &str) -> parser::Node {
+        let mut lexer = Lexer::new(text.to_string());
+        let mut parser = Parser::new(lexer.lex_all());
+        let root = parser.parse();
+        assert!(parser.diagnostics().is_empty());
+        root
+    }
+
+    #[test]
+    fn test_convert_spec() {
+        let root = parse("universe {d1..d3};\nlet K = {d2};\nL1 = ~K;\nL2 = L1 U {d1} / K;");
+        for tree in [root.clone(), crate::node::simplify(&root)] {
+            let spec = Spec::try_from(&tree).unwrap();
+            assert_eq!(spec.universes.len(), 1);
+            assert_eq!(spec.bindings[0].name.name, "K");
+            assert_eq!(spec.equations.len(), 2);
+            assert_eq!(spec.equations[1].lhs.name, "L2");
+            assert!(matches!(spec.equations[0].rhs, Expr::Complement { .. }));
+            let names = spec.equations[1]
+                .rhs
+                .vars()
+                .iter()
+                .map(|ident| ident.name.as_str())
+                .collect::<Vec<_>>();
+            assert_eq!(names, vec!["L1", "K"]);
+        }
+    }
+
+    #[test]
+    fn test_convert_ranges() {
+        let root = parse("L1 = {d1..d3, d7};");
+        let spec = Spec::try_from(&root).unwrap();
+        let Expr::SetLit(set) = &spec.equations[0].rhs else {
+            panic!("expected a set literal");
+        };
+        assert!(matches!(set.elements[0], Element::Range { .. }));
+
+        let spec = Spec::try_from(&crate::node::simplify(&root)).unwrap();
+        let Expr::SetLit(set) = &spec.equations[0].rhs else {
+            panic!("expected a set literal");
+        };
+        assert_eq!(set.elements.len(), 4);
+    }
+
+    #[test]
+    fn test_convert_invalid_tree() {
+        let root = parse("L1 = {d1};");
+        let mut equation = crate::node::simplify(&root)[0].clone();
+        equation.children.pop();
+        let list = parser::Node {
+            node_type: NodeType::DataFlowEquationList,
+            children: vec![equation],
+            token: None,
+        };
+        assert_eq!(
+            Spec::try_from(&list).unwrap_err(),
+            InvalidTree {
+                node_type: NodeType::DataflowEquation
+            }
+        );
+    }
+}
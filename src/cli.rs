@@ -0,0 +1,416 @@
+use std::fmt;
+
+use lattis::analysis::Analysis;
+use lattis::framework::{Config, Mode, Order, Solver, Strategy};
+use lattis::lint::{Level, LintConfig};
+use lattis::unparse::Notation;
+
+pub const EXIT_SUCCESS: u8 = 0;
+pub const EXIT_FAILURE: u8 = 1;
+pub const EXIT_USAGE: u8 = 2;
+pub const EXIT_IO: u8 = 3;
+
+pub const USAGE: &str = "\
+Usage: lattis <COMMAND> [OPTIONS] [FILE]...
+
+Commands:
+  solve        Solve the data flow equations and print every iteration
+  check        Parse the specification and report problems
+  lint         Report suspicious gen/kill patterns
+  fmt          Print the specification in canonical form, keeping comments
+  simplify     Print the specification with every equation simplified
+  orders       Solve once in every evaluation order and compare the counts
+  dump-ast     Print the parsed syntax tree
+  dump-tokens  Print the token stream
+
+Options:
+  -a, --analysis <NAME>  Analysis to run: reaching-definitions, live-variables,
+                         available-expressions [default: reaching-definitions]
+  --solver <SOLVER>      Fixpoint solver: round-robin, or a worklist taken in
+                         fifo, lifo or priority order [default: round-robin]
+  --mode <MODE>          Whether an iteration reads the newest values
+                         (gauss-seidel) or those of the previous iteration
+                         (jacobi) [default: gauss-seidel]
+  --order <ORDER>        Evaluation order: textual, reverse, rpo (reverse
+                         postorder), postorder, scc, random [default: textual]
+  --seed <N>             Seed of the random order [default: 0]
+  -f, --format <FORMAT>  Output format: text, json [default: text]
+  --notation <NOTATION>  Set notation of printed specs: ascii, unicode [default: ascii]
+  --check                With `fmt`, only report files that are not formatted
+  --reduce               With `solve`, remove copy equations before solving
+  -A, --allow <RULE>     Silence a lint rule, or `all` of them
+  -W, --warn <RULE>      Report a lint rule as a warning (the default)
+  -D, --deny <RULE>      Report a lint rule as an error
+  -h, --help             Print this help
+
+Reads standard input when no FILE is given or FILE is `-`.
+
+Exit status:
+  0  success
+  1  the specification has errors
+  2  invalid command line
+  3  an input could not be read
+";
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Command {
+    Solve,
+    Check,
+    Lint,
+    Fmt,
+    Simplify,
+    Orders,
+    DumpAst,
+    DumpTokens,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Format {
+    Text,
+    Json,
+}
+
+#[derive(Debug)]
+pub struct Options {
+    pub command: Command,
+    pub analysis: Analysis,
+    pub config: Config,
+    pub seed: u64,
+    pub format: Format,
+    pub notation: Notation,
+    pub check: bool,
+    pub reduce: bool,
+    pub lints: LintConfig,
+    pub paths: Vec<String>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Error {
+    Help,
+    MissingCommand,
+    UnknownCommand(String),
+    UnknownOption(String),
+    MissingValue(String),
+    InvalidValue { option: String, value: String },
+    Conflict(String, String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Help => write!(f, "help requested"),
+            Error::MissingCommand => write!(f, "no command given"),
+            Error::UnknownCommand(command) => write!(f, "unknown command `{}`", command),
+            Error::UnknownOption(option) => write!(f, "unknown option `{}`", option),
+            Error::MissingValue(option) => write!(f, "option `{}` needs a value", option),
+            Error::InvalidValue { option, value } => {
+                write!(f, "invalid value `{}` for option `{}`", value, option)
+            }
+            Error::Conflict(a, b) => write!(f, "`{}` cannot be used with `{}`", a, b),
+        }
+    }
+}
+
+impl Command {
+    fn from_name(name: &str) -> Option<Command> {
+        match name {
+            "solve" => Some(Command::Solve),
+            "check" => Some(Command::Check),
+            "lint" => Some(Command::Lint),
+            "fmt" => Some(Command::Fmt),
+            "simplify" => Some(Command::Simplify),
+            "orders" => Some(Command::Orders),
+            "dump-ast" => Some(Command::DumpAst),
+            "dump-tokens" => Some(Command::DumpTokens),
+            _ => None,
+        }
+    }
+}
+
+fn analysis_from_name(name: &str) -> Option<Analysis> {
+    match name {
+        "reaching-definitions" | "rd" => Some(Analysis::ReachingDefinitions),
+        "live-variables" | "lv" => Some(Analysis::LiveVariables),
+        "available-expressions" | "ae" => Some(Analysis::AvailableExpressions),
+        _ => None,
+    }
+}
+
+/// Every evaluation order by name, with `seed` for the random one.
+pub fn orders(seed: u64) -> [(&'static str, Order); 6] {
+    [
+        ("textual", Order::Textual),
+        ("reverse", Order::Reverse),
+        ("rpo", Order::ReversePostorder),
+        ("postorder", Order::Postorder),
+        ("scc", Order::Components),
+        ("random", Order::Random { seed }),
+    ]
+}
+
+fn order_from_name(name: &str) -> Option<Order> {
+    orders(0)
+        .into_iter()
+        .find(|(order, _)| *order == name)
+        .map(|(_, order)| order)
+}
+
+fn mode_from_name(name: &str) -> Option<Mode> {
+    match name {
+        "gauss-seidel" => Some(Mode::GaussSeidel),
+        "jacobi" => Some(Mode::Jacobi),
+        _ => None,
+    }
+}
+
+fn solver_from_name(name: &str) -> Option<Solver> {
+    match name {
+        "round-robin" => Some(Solver::RoundRobin),
+        "fifo" => Some(Solver::Worklist(Strategy::Fifo)),
+        "lifo" => Some(Solver::Worklist(Strategy::Lifo)),
+        "priority" => Some(Solver::Worklist(Strategy::Priority)),
+        _ => None,
+    }
+}
+
+impl Format {
+    fn from_name(name: &str) -> Option<Format> {
+        match name {
+            "text" => Some(Format::Text),
+            "json" => Some(Format::Json),
+            _ => None,
+        }
+    }
+}
+
+fn notation_from_name(name: &str) -> Option<Notation> {
+    match name {
+        "ascii" => Some(Notation::Ascii),
+        "unicode" => Some(Notation::Unicode),
+        _ => None,
+    }
+}
+
+pub fn parse_args(args: &[String]) -> Result<Options, Error> {
+    let mut command = None;
+    let mut analysis = Analysis::ReachingDefinitions;
+    let mut config = Config::default();
+    let mut solver_name = String::new();
+    let mut seed = 0;
+    let mut format = Format::Text;
+    let mut notation = Notation::Ascii;
+    let mut check = false;
+    let mut reduce = false;
+    let mut lints = LintConfig::new();
+    let mut paths = Vec::new();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let (flag, inline_value) = match arg.split_once('=') {
+            Some((flag, value)) if arg.starts_with("--") => (flag, Some(value.to_string())),
+            _ => (arg.as_str(), None),
+        };
+
+        match flag {
+            "-h" | "--help" => return Err(Error::Help),
+            "-a" | "--analysis" => {
+                let value = option_value(flag, inline_value, &mut args)?;
+                analysis = analysis_from_name(&value).ok_or(Error::InvalidValue {
+                    option: flag.to_string(),
+                    value,
+                })?;
+            }
+            "--solver" => {
+                let value = option_value(flag, inline_value, &mut args)?;
+                config.solver = solver_from_name(&value).ok_or(Error::InvalidValue {
+                    option: flag.to_string(),
+                    value: value.clone(),
+                })?;
+                solver_name = value;
+            }
+            "--mode" => {
+                let value = option_value(flag, inline_value, &mut args)?;
+                config.mode = mode_from_name(&value).ok_or(Error::InvalidValue {
+                    option: flag.to_string(),
+                    value,
+                })?;
+            }
+            "--order" => {
+                let value = option_value(flag, inline_value, &mut args)?;
+                config.order = order_from_name(&value).ok_or(Error::InvalidValue {
+                    option: flag.to_string(),
+                    value,
+                })?;
+            }
+            "--seed" => {
+                let value = option_value(flag, inline_value, &mut args)?;
+                seed = value.parse().map_err(|_| Error::InvalidValue {
+                    option: flag.to_string(),
+                    value,
+                })?;
+            }
+            "-f" | "--format" => {
+                let value = option_value(flag, inline_value, &mut args)?;
+                format = Format::from_name(&value).ok_or(Error::InvalidValue {
+                    option: flag.to_string(),
+                    value,
+                })?;
+            }
+            "--notation" => {
+                let value = option_value(flag, inline_value, &mut args)?;
+                notation = notation_from_name(&value).ok_or(Error::InvalidValue {
+                    option: flag.to_string(),
+                    value,
+                })?;
+            }
+            "--check" => check = true,
+            "--reduce" => reduce = true,
+            "-A" | "--allow" | "-W" | "--warn" | "-D" | "--deny" => {
+                let level = match flag {
+                    "-A" | "--allow" => Level::Allow,
+                    "-W" | "--warn" => Level::Warn,
+                    _ => Level::Deny,
+                };
+                let value = option_value(flag, inline_value, &mut args)?;
+                if lints.set(&value, level).is_err() {
+                    return Err(Error::InvalidValue {
+                        option: flag.to_string(),
+                        value,
+                    });
+                }
+            }
+            "-" => paths.push(arg.clone()),
+            _ if arg.starts_with('-') => return Err(Error::UnknownOption(arg.clone())),
+            _ if command.is_none() => {
+                command = Some(
+                    Command::from_name(arg).ok_or_else(|| Error::UnknownCommand(arg.clone()))?,
+                );
+            }
+            _ => paths.push(arg.clone()),
+        }
+    }
+
+    if let Order::Random { .. } = config.order {
+        config.order = Order::Random { seed };
+    }
+    // A worklist evaluates one equation at a time, so it has no rounds to
+    // read old values from.
+    if config.mode == Mode::Jacobi && config.solver != Solver::RoundRobin {
+        return Err(Error::Conflict(
+            "--mode jacobi".to_string(),
+            format!("--solver {}", solver_name),
+        ));
+    }
+
+    Ok(Options {
+        seed,
+        command: command.ok_or(Error::MissingCommand)?,
+        analysis,
+        config,
+        format,
+        notation,
+        check,
+        reduce,
+        lints,
+        paths,
+    })
+}
+
+fn option_value<'a>(
+    flag: &str,
+    inline_value: Option<String>,
+    args: &mut impl Iterator<Item = &'a String>,
+) -> Result<String, Error> {
+    match inline_value {
+        Some(value) => Ok(value),
+        None => args
+            .next()
+            .cloned()
+            .ok_or_else(|| Error::MissingValue(flag.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_args_defaults() {
+        let options = parse_args(&args(&["solve", "oppg.txt"])).unwrap();
+        assert_eq!(options.command, Command::Solve);
+        assert_eq!(options.analysis, Analysis::ReachingDefinitions);
+        assert_eq!(options.format, Format::Text);
+        assert_eq!(options.paths, vec!["oppg.txt"]);
+    }
+
+    #[test]
+    fn test_parse_args_options() {
+        let options = parse_args(&args(&["solve", "--format=json", "-a", "rd", "a", "-"])).unwrap();
+        assert_eq!(options.format, Format::Json);
+        assert_eq!(options.notation, Notation::Ascii);
+        assert_eq!(options.paths, vec!["a", "-"]);
+        let options = parse_args(&args(&["fmt", "--notation", "unicode", "--check"])).unwrap();
+        assert_eq!(options.notation, Notation::Unicode);
+        assert!(options.check);
+        let options = parse_args(&args(&["solve", "--reduce", "-a", "ae"])).unwrap();
+        assert!(options.reduce);
+        assert_eq!(options.analysis, Analysis::AvailableExpressions);
+        let options = parse_args(&args(&["solve", "--solver=lifo"])).unwrap();
+        assert_eq!(options.config.solver, Solver::Worklist(Strategy::Lifo));
+        let options = parse_args(&args(&["solve", "--mode", "jacobi"])).unwrap();
+        assert_eq!(options.config.mode, Mode::Jacobi);
+        let options = parse_args(&args(&["solve", "--seed=42", "--order", "random"])).unwrap();
+        assert_eq!(options.config.order, Order::Random { seed: 42 });
+        let options = parse_args(&args(&["orders", "--order=rpo"])).unwrap();
+        assert_eq!(options.command, Command::Orders);
+        assert_eq!(options.config.order, Order::ReversePostorder);
+        let options = parse_args(&args(&["simplify", "--notation=unicode"])).unwrap();
+        assert_eq!(options.command, Command::Simplify);
+        assert_eq!(options.notation, Notation::Unicode);
+        let options = parse_args(&args(&["lint", "-A", "all", "--deny=noop-kill"])).unwrap();
+        assert_eq!(options.lints.level("simplifiable"), Level::Allow);
+        assert_eq!(options.lints.level("noop-kill"), Level::Deny);
+    }
+
+    #[test]
+    fn test_parse_args_errors() {
+        assert_eq!(parse_args(&args(&[])).unwrap_err(), Error::MissingCommand);
+        assert_eq!(
+            parse_args(&args(&["run"])).unwrap_err(),
+            Error::UnknownCommand("run".to_string())
+        );
+        assert_eq!(
+            parse_args(&args(&["solve", "--format"])).unwrap_err(),
+            Error::MissingValue("--format".to_string())
+        );
+        assert_eq!(
+            parse_args(&args(&["solve", "-f", "xml"])).unwrap_err(),
+            Error::InvalidValue {
+                option: "-f".to_string(),
+                value: "xml".to_string()
+            }
+        );
+        assert_eq!(
+            parse_args(&args(&["lint", "-D", "typo"])).unwrap_err(),
+            Error::InvalidValue {
+                option: "-D".to_string(),
+                value: "typo".to_string()
+            }
+        );
+        assert_eq!(
+            parse_args(&args(&["solve", "--seed", "-1"])).unwrap_err(),
+            Error::InvalidValue {
+                option: "--seed".to_string(),
+                value: "-1".to_string()
+            }
+        );
+        assert_eq!(
+            parse_args(&args(&["solve", "--mode=jacobi", "--solver=fifo"])).unwrap_err(),
+            Error::Conflict("--mode jacobi".to_string(), "--solver fifo".to_string())
+        );
+    }
+}
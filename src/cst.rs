@@ -0,0 +1,329 @@
+//! A lossless concrete syntax tree.
+//!
+//! Unlike the tree the parser builds, the concrete syntax tree
+//! keeps every character of the source: whitespace and comments are trivia
+//! tokens, and parentheses and set braces stay in the tree. Printing a tree
+//! with [`SyntaxNode::text`] gives back the exact text it was built from,
+//! which is what the formatter relies on to keep comments.
+
+use crate::lexer::{Lexer, Token, TokenType};
+use crate::span::Span;
+
+/// What a [`SyntaxToken`] is.
+#[derive(Debug, PartialEq, Clone)]
+pub enum SyntaxKind {
+    /// A token the parser sees.
+    Token(TokenType),
+    /// Spaces, tabs and line breaks.
+    Whitespace,
+    /// `// ...`, without the line break.
+    LineComment,
+    /// `/* ... */`
+    BlockComment,
+    /// A character the lexer reported and skipped.
+    Unknown,
+}
+
+/// A token or a piece of trivia, with its exact text.
+#[derive(Debug, PartialEq, Clone)]
+pub struct SyntaxToken {
+    /// What the token is.
+    pub kind: SyntaxKind,
+    /// The source text of the token.
+    pub text: String,
+    /// Where the token is.
+    pub span: Span,
+}
+
+impl SyntaxToken {
+    /// Whether the parser skips the token.
+    pub fn is_trivia(&self) -> bool {
+        !matches!(self.kind, SyntaxKind::Token(_))
+    }
+
+    /// The type of a token the parser sees.
+    pub fn token_type(&self) -> Option<&TokenType> {
+        match &self.kind {
+            SyntaxKind::Token(token_type) => Some(token_type),
+            _ => None,
+        }
+    }
+}
+
+/// What a [`SyntaxNode`] is.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum NodeKind {
+    /// The whole file: statements and the trivia between them.
+    Spec,
+    /// An equation, `let` binding, `universe` or `include`, up to and
+    /// including its `;` if it has one.
+    Statement,
+    /// `{ ... }`
+    Set,
+    /// `( ... )`
+    Group,
+}
+
+/// A child of a [`SyntaxNode`].
+#[derive(Debug, PartialEq, Clone)]
+pub enum SyntaxElement {
+    /// A nested node.
+    Node(SyntaxNode),
+    /// A token or trivia.
+    Token(SyntaxToken),
+}
+
+/// A node of the concrete syntax tree.
+#[derive(Debug, PartialEq, Clone)]
+pub struct SyntaxNode {
+    /// What the node is.
+    pub kind: NodeKind,
+    /// Everything in the node, in source order.
+    pub children: Vec<SyntaxElement>,
+}
+
+impl SyntaxNode {
+    /// The source text covered by the node, trivia included.
+    pub fn text(&self) -> String {
+        let mut text = String::new();
+        self.write_text(&mut text);
+        text
+    }
+
+    fn write_text(&self, text: &mut String) {
+        for child in &self.children {
+            match child {
+                SyntaxElement::Node(node) => node.write_text(text),
+                SyntaxElement::Token(token) => text.push_str(&token.text),
+            }
+        }
+    }
+}
+
+/// Builds the concrete syntax tree of `text`. This never fails; problems are
+/// left for the parser to report.
+pub fn parse(text: &str) -> SyntaxNode {
+    let mut lexer = Lexer::new(text.to_string());
+    let tokens = lexer.lex_all();
+
+    let mut elements = Vec::new();
+    let mut statement = Vec::new();
+    let mut position = 0;
+    let mut significant = tokens
+        .iter()
+        .filter(|token| token.token_type != TokenType::Eof)
+        .peekable();
+    while let Some(token) = significant.next() {
+        // Trivia between statements belongs to the file, not a statement.
+        let mut trivia = Vec::new();
+        elements_between(text, position, token.span.start, &mut trivia);
+        if statement.is_empty() {
+            elements.extend(trivia.into_iter().map(SyntaxElement::Token));
+        } else {
+            statement.extend(trivia);
+        }
+        statement.push(SyntaxToken {
+            kind: SyntaxKind::Token(token.token_type.clone()),
+            text: token.lexeme.clone(),
+            span: token.span,
+        });
+        position = token.span.end;
+
+        if ends_statement(token, significant.peek().copied(), &statement) {
+            elements.push(SyntaxElement::Node(SyntaxNode {
+                kind: NodeKind::Statement,
+                children: nest(std::mem::take(&mut statement).into_iter()),
+            }));
+        }
+    }
+    let mut trivia = Vec::new();
+    elements_between(text, position, text.len(), &mut trivia);
+    elements.extend(trivia.into_iter().map(SyntaxElement::Token));
+
+    SyntaxNode {
+        kind: NodeKind::Spec,
+        children: elements,
+    }
+}
+
+// Whether the statement in `statement`, whose last token is `token`, ends
+// before `next`. Mirrors the parser: a statement ends at `;`, or before a
+// token on a new line unless that token or the one before it is an operator.
+fn ends_statement(token: &Token, next: Option<&Token>, statement: &[SyntaxToken]) -> bool {
+    let next = match next {
+        Some(next) => next,
+        None => return true,
+    };
+    if token.token_type == TokenType::NewLine {
+        return true;
+    }
+
+    let depth =
+        statement
+            .iter()
+            .filter_map(SyntaxToken::token_type)
+            .fold(0i32, |depth, token_type| match token_type {
+                TokenType::SetOpen | TokenType::ParenOpen => depth + 1,
+                TokenType::SetClose | TokenType::ParenClose => depth - 1,
+                _ => depth,
+            });
+    let is_operator = |token_type: &TokenType| {
+        matches!(
+            token_type,
+            TokenType::Union | TokenType::Intersection | TokenType::SetDifference
+        )
+    };
+    let wants_operand = is_operator(&token.token_type)
+        || matches!(
+            token.token_type,
+            TokenType::Equals
+                | TokenType::Subset
+                | TokenType::Complement
+                | TokenType::Let
+                | TokenType::UniverseKw
+                | TokenType::Include
+        );
+    depth <= 0 && next.starts_line && !is_operator(&next.token_type) && !wants_operand
+}
+
+// Splits the text between two tokens into whitespace, comments and the
+// unknown characters the lexer skipped.
+fn elements_between(text: &str, start: usize, end: usize, tokens: &mut Vec<SyntaxToken>) {
+    let mut line = 1 + text[..start].matches('\n').count();
+    let mut line_start = text[..start].rfind('\n').map_or(0, |i| i + 1);
+    let mut cursor = start;
+    while cursor < end {
+        let rest = &text[cursor..end];
+        let (kind, len) = if rest.starts_with("//") {
+            (
+                SyntaxKind::LineComment,
+                rest.find('\n').unwrap_or(rest.len()),
+            )
+        } else if let Some(comment) = rest.strip_prefix("/*") {
+            let len = comment.find("*/").map_or(rest.len(), |i| i + 4);
+            (SyntaxKind::BlockComment, len)
+        } else if rest.starts_with(char::is_whitespace) {
+            let len = rest
+                .find(|c: char| !c.is_whitespace())
+                .unwrap_or(rest.len());
+            (SyntaxKind::Whitespace, len)
+        } else {
+            let c = rest.chars().next().unwrap();
+            (SyntaxKind::Unknown, c.len_utf8())
+        };
+
+        let token_text = &rest[..len];
+        tokens.push(SyntaxToken {
+            kind,
+            text: token_text.to_string(),
+            span: Span {
+                start: cursor,
+                end: cursor + len,
+                line,
+                column: text[line_start..cursor].chars().count() + 1,
+                file: 0,
+            },
+        });
+        if let Some(last) = token_text.rfind('\n') {
+            line += token_text.matches('\n').count();
+            line_start = cursor + last + 1;
+        }
+        cursor += len;
+    }
+}
+
+// Groups `{ ... }` and `( ... )` into nodes. Unbalanced brackets are left as
+// plain tokens.
+fn nest(tokens: impl Iterator<Item = SyntaxToken>) -> Vec<SyntaxElement> {
+    let mut stack: Vec<(NodeKind, Vec<SyntaxElement>)> = vec![(NodeKind::Statement, Vec::new())];
+    for token in tokens {
+        match token.token_type() {
+            Some(TokenType::SetOpen) => {
+                stack.push((NodeKind::Set, vec![SyntaxElement::Token(token)]))
+            }
+            Some(TokenType::ParenOpen) => {
+                stack.push((NodeKind::Group, vec![SyntaxElement::Token(token)]))
+            }
+            Some(TokenType::SetClose) | Some(TokenType::ParenClose)
+                if stack.len() > 1 && closes(stack.last().unwrap().0, token.token_type()) =>
+            {
+                let (kind, mut children) = stack.pop().unwrap();
+                children.push(SyntaxElement::Token(token));
+                let node = SyntaxElement::Node(SyntaxNode { kind, children });
+                stack.last_mut().unwrap().1.push(node);
+            }
+            _ => stack
+                .last_mut()
+                .unwrap()
+                .1
+                .push(SyntaxElement::Token(token)),
+        }
+    }
+
+    // Flatten whatever was left open.
+    while stack.len() > 1 {
+        let (_, children) = stack.pop().unwrap();
+        stack.last_mut().unwrap().1.extend(children);
+    }
+    stack.pop().unwrap().1
+}
+
+fn closes(kind: NodeKind, token_type: Option<&TokenType>) -> bool {
+    matches!(
+        (kind, token_type),
+        (NodeKind::Set, Some(TokenType::SetClose)) | (NodeKind::Group, Some(TokenType::ParenClose))
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn statements(spec: &SyntaxNode) -> Vec<String> {
+        spec.children
+            .iter()
+            .filter_map(|child| match child {
+                SyntaxElement::Node(node) => Some(node.text()),
+                SyntaxElement::Token(_) => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_cst_is_lossless() {
+        let text =
+            "// header\nL1 = {d2,d1} ; /* x */ L2 = (L1\n  U {d3}) // tail\n\nL3 = ~ L2 # \n";
+        let spec = parse(text);
+        assert_eq!(spec.text(), text);
+        assert_eq!(
+            statements(&spec),
+            vec!["L1 = {d2,d1} ;", "L2 = (L1\n  U {d3})", "L3 = ~ L2"]
+        );
+    }
+
+    #[test]
+    fn test_cst_statements_across_lines() {
+        let spec = parse("L1 = L2\n  U L3\nL4 =\n  L1");
+        assert_eq!(statements(&spec), vec!["L1 = L2\n  U L3", "L4 =\n  L1"]);
+    }
+
+    #[test]
+    fn test_cst_nesting() {
+        let spec = parse("L1 = ({d1} U L2);");
+        let SyntaxElement::Node(statement) = &spec.children[0] else {
+            panic!("expected a statement");
+        };
+        let group = statement
+            .children
+            .iter()
+            .find_map(|child| match child {
+                SyntaxElement::Node(node) => Some(node),
+                SyntaxElement::Token(_) => None,
+            })
+            .unwrap();
+        assert_eq!(group.kind, NodeKind::Group);
+        assert!(
+            matches!(&group.children[1], SyntaxElement::Node(set) if set.kind == NodeKind::Set)
+        );
+    }
+}
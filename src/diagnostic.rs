@@ -0,0 +1,263 @@
+//! Errors and warnings about a specification, and how they are printed.
+
+use std::fmt;
+
+use crate::source::SourceMap;
+use crate::span::Span;
+
+/// How serious a [`Diagnostic`] is.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Severity {
+    /// The specification cannot be used.
+    Error,
+    /// The specification works, but probably not as meant.
+    Warning,
+}
+
+/// A single problem found in a specification.
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    /// Whether the problem is an error or a warning.
+    pub severity: Severity,
+    /// The lint rule that produced the diagnostic, if any.
+    pub code: Option<String>,
+    /// What is wrong, in one line.
+    pub message: String,
+    /// Where the problem is, if it is anywhere in particular.
+    pub span: Option<Span>,
+    /// A short note printed next to the caret under `span`.
+    pub label: Option<String>,
+    /// A suggestion on how to fix the problem.
+    pub help: Option<String>,
+}
+
+impl Diagnostic {
+    /// An error saying `message`.
+    pub fn error(message: impl Into<String>) -> Diagnostic {
+        Diagnostic {
+            severity: Severity::Error,
+            code: None,
+            message: message.into(),
+            span: None,
+            label: None,
+            help: None,
+        }
+    }
+
+    /// A warning saying `message`.
+    pub fn warning(message: impl Into<String>) -> Diagnostic {
+        Diagnostic {
+            severity: Severity::Warning,
+            ..Diagnostic::error(message)
+        }
+    }
+
+    /// Names the lint rule that produced the diagnostic.
+    pub fn with_code(mut self, code: impl Into<String>) -> Diagnostic {
+        self.code = Some(code.into());
+        self
+    }
+
+    /// Points at `span` without a label.
+    pub fn with_span(mut self, span: Span) -> Diagnostic {
+        self.span = Some(span);
+        self
+    }
+
+    /// Points at `span` with a short `label` printed next to the caret.
+    pub fn with_label(mut self, span: Span, label: impl Into<String>) -> Diagnostic {
+        self.span = Some(span);
+        self.label = Some(label.into());
+        self
+    }
+
+    /// Adds a suggestion on how to fix the problem.
+    pub fn with_help(mut self, help: impl Into<String>) -> Diagnostic {
+        self.help = Some(help.into());
+        self
+    }
+
+    /// Renders the diagnostic like rustc does, quoting the offending line of
+    /// `source` with a caret under the span.
+    ///
+    /// ```text
+    /// error: expected `=`, found `U`
+    ///  --> oppg.txt:3:4
+    ///   |
+    /// 3 | L3 U L2;
+    ///   |    ^ expected `=`
+    /// ```
+    pub fn render(&self, name: &str, source: &str) -> String {
+        let mut out = format!("{}: {}\n", self.heading(), self.message);
+        let span = match self.span {
+            Some(span) => span,
+            None => {
+                out.push_str(&format!(" --> {}\n", name));
+                if let Some(help) = &self.help {
+                    out.push_str(&format!("  = help: {}\n", help));
+                }
+                return out;
+            }
+        };
+
+        let line_start = source[..span.start.min(source.len())]
+            .rfind('\n')
+            .map_or(0, |i| i + 1);
+        let line_end = source[line_start..]
+            .find('\n')
+            .map_or(source.len(), |i| line_start + i);
+        let line = source[line_start..line_end].trim_end_matches('\r');
+
+        let start = span.start.clamp(line_start, line_start + line.len());
+        let end = span.end.clamp(start, line_start + line.len());
+        let offset = source[line_start..start].chars().count();
+        let carets = source[start..end].chars().count().max(1);
+
+        let number = span.line.to_string();
+        let gutter = " ".repeat(number.len());
+        out.push_str(&format!(
+            "{}--> {}:{}:{}\n",
+            gutter, name, span.line, span.column
+        ));
+        out.push_str(&format!("{} |\n", gutter));
+        out.push_str(&format!("{} | {}\n", number, line));
+        out.push_str(&format!(
+            "{} | {}{}",
+            gutter,
+            " ".repeat(offset),
+            "^".repeat(carets)
+        ));
+        if let Some(label) = &self.label {
+            out.push_str(&format!(" {}", label));
+        }
+        out.push('\n');
+        if let Some(help) = &self.help {
+            out.push_str(&format!("{} = help: {}\n", gutter, help));
+        }
+        out
+    }
+}
+
+impl Diagnostic {
+    // `error` or, for a lint, `warning[rule]`.
+    fn heading(&self) -> String {
+        match &self.code {
+            Some(code) => format!("{}[{}]", self.severity, code),
+            None => self.severity.to_string(),
+        }
+    }
+
+    /// Renders the diagnostic against the file its span points into.
+    pub fn render_in(&self, sources: &SourceMap) -> String {
+        let file = sources.get(self.span.map_or(0, |span| span.file));
+        self.render(&file.name, &file.text)
+    }
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(span) = self.span {
+            write!(f, "{}:{}: ", span.line, span.column)?;
+        }
+        write!(f, "{}: {}", self.heading(), self.message)
+    }
+}
+
+/// Every diagnostic reported for one specification.
+#[derive(Debug, Clone, Default)]
+pub struct Diagnostics(Vec<Diagnostic>);
+
+impl Diagnostics {
+    /// No diagnostics.
+    pub fn new() -> Diagnostics {
+        Diagnostics(Vec::new())
+    }
+
+    /// Adds `diagnostic` after the others.
+    pub fn push(&mut self, diagnostic: Diagnostic) {
+        self.0.push(diagnostic);
+    }
+
+    /// The diagnostics in the order they were added.
+    pub fn iter(&self) -> std::slice::Iter<'_, Diagnostic> {
+        self.0.iter()
+    }
+
+    /// Number of diagnostics, warnings included.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Whether nothing was reported.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Whether any diagnostic is an error.
+    pub fn has_errors(&self) -> bool {
+        self.0.iter().any(|d| d.severity == Severity::Error)
+    }
+}
+
+impl<'a> IntoIterator for &'a Diagnostics {
+    type Item = &'a Diagnostic;
+    type IntoIter = std::slice::Iter<'a, Diagnostic>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter()
+    }
+}
+
+impl From<Vec<Diagnostic>> for Diagnostics {
+    fn from(diagnostics: Vec<Diagnostic>) -> Diagnostics {
+        Diagnostics(diagnostics)
+    }
+}
+
+impl fmt::Display for Diagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for diagnostic in &self.0 {
+            writeln!(f, "{}", diagnostic)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for Diagnostics {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_caret() {
+        let source = "L1 = {};\nL2 L1 U {d1};\n";
+        let span = Span {
+            start: 12,
+            end: 14,
+            line: 2,
+            column: 4,
+            file: 0,
+        };
+        let diagnostic = Diagnostic::error("expected `=`, found data point `L1`")
+            .with_label(span, "expected `=`");
+        let expected = [
+            "error: expected `=`, found data point `L1`",
+            " --> oppg.txt:2:4",
+            "  |",
+            "2 | L2 L1 U {d1};",
+            "  |    ^^ expected `=`",
+            "",
+        ];
+        assert_eq!(diagnostic.render("oppg.txt", source), expected.join("\n"));
+    }
+}
@@ -0,0 +1,321 @@
+//! The canonical layout of a specification, as printed by `lattis fmt`.
+//!
+//! Formatting works on the [concrete syntax tree](crate::cst) so that
+//! comments survive. Every statement goes on its own line and ends in `;`,
+//! operators get a space on each side, set literals are sorted in natural
+//! order and at most one blank line is kept between statements. Parentheses
+//! are kept as written.
+
+use std::cmp::Ordering;
+
+use crate::cst::{self, NodeKind, SyntaxElement, SyntaxKind, SyntaxNode, SyntaxToken};
+use crate::diagnostic::Diagnostics;
+use crate::lexer::{Lexer, TokenType};
+use crate::names::natural_cmp;
+use crate::parser::Parser;
+use crate::unparse::Notation;
+
+/// Formats `text`, which must be free of syntax errors. Included files are
+/// not read, and semantic errors such as undefined names are not reported.
+pub fn format(text: &str, notation: Notation) -> Result<String, Diagnostics> {
+    let mut lexer = Lexer::new(text.to_string());
+    let mut parser = Parser::new(lexer.lex_all());
+    parser.parse();
+    let mut diagnostics = lexer.diagnostics().to_vec();
+    diagnostics.extend_from_slice(parser.diagnostics());
+    if !diagnostics.is_empty() {
+        diagnostics.sort_by_key(|d| d.span.map(|span| span.start));
+        return Err(diagnostics.into());
+    }
+
+    Ok(format_spec(&cst::parse(text), notation))
+}
+
+fn format_spec(spec: &SyntaxNode, notation: Notation) -> String {
+    let mut out = String::new();
+    // Whether the last line of `out` is still open.
+    let mut line_open = false;
+    let mut blank_line = false;
+
+    for child in &spec.children {
+        match child {
+            SyntaxElement::Token(token) if token.kind == SyntaxKind::Whitespace => {
+                let newlines = token.text.matches('\n').count();
+                if newlines > 0 && line_open {
+                    out.push('\n');
+                    line_open = false;
+                }
+                blank_line |= newlines > 1 && !out.is_empty();
+            }
+            // A comment after a statement stays on its line.
+            SyntaxElement::Token(token) if line_open => {
+                out.push(' ');
+                out.push_str(token.text.trim_end());
+            }
+            SyntaxElement::Token(token) => {
+                if std::mem::take(&mut blank_line) {
+                    out.push('\n');
+                }
+                out.push_str(token.text.trim_end());
+                line_open = true;
+            }
+            SyntaxElement::Node(statement) => {
+                if line_open {
+                    out.push('\n');
+                }
+                if std::mem::take(&mut blank_line) {
+                    out.push('\n');
+                }
+                let mut pieces = Vec::new();
+                statement_pieces(statement, notation, &mut pieces);
+                if pieces.last().map(|piece| piece.text.as_str()) != Some(";") {
+                    pieces.push(Piece::new(";", Glue::Left));
+                }
+                out.push_str(&join(&pieces));
+                line_open = true;
+            }
+        }
+    }
+    if line_open {
+        out.push('\n');
+    }
+    out
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Glue {
+    None,
+    Left,
+    Right,
+    Both,
+    // A line comment; the statement continues on the next line.
+    Newline,
+}
+
+struct Piece {
+    text: String,
+    glue: Glue,
+}
+
+impl Piece {
+    fn new(text: impl Into<String>, glue: Glue) -> Piece {
+        Piece {
+            text: text.into(),
+            glue,
+        }
+    }
+}
+
+fn join(pieces: &[Piece]) -> String {
+    let mut out = String::new();
+    for (i, piece) in pieces.iter().enumerate() {
+        if i > 0 {
+            let previous = pieces[i - 1].glue;
+            if previous == Glue::Newline {
+                out.push_str("\n    ");
+            } else if !matches!(previous, Glue::Right | Glue::Both)
+                && !matches!(piece.glue, Glue::Left | Glue::Both)
+            {
+                out.push(' ');
+            }
+        }
+        out.push_str(&piece.text);
+    }
+    out
+}
+
+fn statement_pieces(node: &SyntaxNode, notation: Notation, pieces: &mut Vec<Piece>) {
+    for child in &node.children {
+        match child {
+            SyntaxElement::Node(set) if set.kind == NodeKind::Set => {
+                set_pieces(set, notation, pieces)
+            }
+            SyntaxElement::Node(node) => statement_pieces(node, notation, pieces),
+            SyntaxElement::Token(token) => pieces.extend(token_piece(token, notation)),
+        }
+    }
+}
+
+// `{d3, d1..d2}` becomes `{d1..d2, d3}`; `{}` and `∅` follow the notation.
+fn set_pieces(set: &SyntaxNode, notation: Notation, pieces: &mut Vec<Piece>) {
+    let inner = &set.children[1..set.children.len() - 1];
+    let mut elements: Vec<SetElement> = Vec::new();
+    let slices = inner.split(|child| {
+        matches!(child, SyntaxElement::Token(token) if token.token_type() == Some(&TokenType::Comma))
+    });
+    for slice in slices {
+        let mut children = slice;
+        if let Some((i, comment)) = trailing_comment(slice) {
+            if let Some(previous) = elements.last_mut() {
+                previous.comment = Some(comment);
+                children = &slice[i + 1..];
+            }
+        }
+        if children.iter().any(|child| !is_whitespace(child)) {
+            elements.push(SetElement {
+                children,
+                comment: None,
+            });
+        }
+    }
+    if elements.is_empty() {
+        pieces.push(Piece::new(empty_set(notation), Glue::None));
+        return;
+    }
+    elements.sort_by(|a, b| compare_elements(a.children, b.children));
+
+    pieces.push(Piece::new("{", Glue::Right));
+    for (i, element) in elements.iter().enumerate() {
+        for child in element.children {
+            if let SyntaxElement::Token(token) = child {
+                pieces.extend(token_piece(token, notation));
+            }
+        }
+        if i + 1 < elements.len() {
+            pieces.push(Piece::new(",", Glue::Left));
+        }
+        if let Some(comment) = element.comment {
+            pieces.extend(token_piece(comment, notation));
+        }
+    }
+    pieces.push(Piece::new("}", Glue::Left));
+}
+
+// An element of a set literal with the line comment after its comma, which
+// moves with it when the elements are sorted.
+struct SetElement<'a> {
+    children: &'a [SyntaxElement],
+    comment: Option<&'a SyntaxToken>,
+}
+
+// A line comment on the same line as the comma before `children`, with its
+// position.
+fn trailing_comment(children: &[SyntaxElement]) -> Option<(usize, &SyntaxToken)> {
+    for (i, child) in children.iter().enumerate() {
+        match child {
+            SyntaxElement::Token(token)
+                if token.kind == SyntaxKind::Whitespace && !token.text.contains('\n') => {}
+            SyntaxElement::Token(token) if token.kind == SyntaxKind::LineComment => {
+                return Some((i, token));
+            }
+            _ => return None,
+        }
+    }
+    None
+}
+
+fn is_whitespace(child: &SyntaxElement) -> bool {
+    matches!(child, SyntaxElement::Token(token) if token.kind == SyntaxKind::Whitespace)
+}
+
+// Elements are ordered by their first definition.
+fn compare_elements(a: &[SyntaxElement], b: &[SyntaxElement]) -> Ordering {
+    let first = |element: &[SyntaxElement]| {
+        element
+            .iter()
+            .find_map(|child| match child {
+                SyntaxElement::Token(token) if !token.is_trivia() => Some(token.text.clone()),
+                _ => None,
+            })
+            .unwrap_or_default()
+    };
+    natural_cmp(&first(a), &first(b))
+}
+
+fn empty_set(notation: Notation) -> &'static str {
+    match notation {
+        Notation::Ascii => "{}",
+        Notation::Unicode => "∅",
+    }
+}
+
+fn token_piece(token: &SyntaxToken, notation: Notation) -> Option<Piece> {
+    let unicode = notation == Notation::Unicode;
+    let token_type = match &token.kind {
+        SyntaxKind::Whitespace | SyntaxKind::Unknown => return None,
+        SyntaxKind::LineComment => return Some(Piece::new(token.text.trim_end(), Glue::Newline)),
+        SyntaxKind::BlockComment => return Some(Piece::new(token.text.clone(), Glue::None)),
+        SyntaxKind::Token(token_type) => token_type,
+    };
+    let (text, glue) = match token_type {
+        TokenType::Union => (if unicode { "∪" } else { "U" }, Glue::None),
+        TokenType::Intersection => (if unicode { "∩" } else { "I" }, Glue::None),
+        TokenType::SetDifference => (if unicode { "∖" } else { "/" }, Glue::None),
+        TokenType::EmptySet => (empty_set(notation), Glue::None),
+        TokenType::ParenOpen | TokenType::SetOpen | TokenType::Complement => {
+            (token.text.as_str(), Glue::Right)
+        }
+        TokenType::ParenClose | TokenType::SetClose | TokenType::Comma | TokenType::NewLine => {
+            (token.text.as_str(), Glue::Left)
+        }
+        TokenType::Range => ("..", Glue::Both),
+        _ => (token.text.as_str(), Glue::None),
+    };
+    Some(Piece::new(text, glue))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fmt(text: &str) -> String {
+        format(text, Notation::Ascii).unwrap()
+    }
+
+    #[test]
+    fn test_format_spacing_and_sets() {
+        assert_eq!(
+            fmt("L14 = L13/{d4,d6,d5} U {d10,d7..d9}/{d7}"),
+            "L14 = L13 / {d4, d5, d6} U {d7..d9, d10} / {d7};\n"
+        );
+        assert_eq!(
+            fmt("universe {d1..d3};let K={ };L1 = ~ ( K ∪ L1 );"),
+            "universe {d1..d3};\nlet K = {};\nL1 = ~(K U L1);\n"
+        );
+    }
+
+    #[test]
+    fn test_format_keeps_comments() {
+        let text = "// header\n\n\n\nL1 = {} ; // entry\n/* block */\nL2 = L1 U\n  {d1}\n\n";
+        let expected = "// header\n\nL1 = {}; // entry\n/* block */\nL2 = L1 U {d1};\n";
+        assert_eq!(fmt(text), expected);
+        assert_eq!(fmt(expected), expected);
+    }
+
+    #[test]
+    fn test_format_comment_inside_equation() {
+        let text = "L2 = L1 // from L1\n  U {d2, /* kill */ d1};";
+        let expected = "L2 = L1 // from L1\n    U {/* kill */ d1, d2};\n";
+        assert_eq!(fmt(text), expected);
+        assert_eq!(fmt(expected), expected);
+    }
+
+    #[test]
+    fn test_format_comments_move_with_elements() {
+        let text = "L1 = {d3, // three\n  d2, /* two */ d1};";
+        let expected = "L1 = {/* two */ d1, d2, d3 // three\n    };\n";
+        assert_eq!(fmt(text), expected);
+        assert_eq!(fmt(expected), expected);
+
+        let text = "L1 = {d2, // two\n  // one\n  d1};";
+        let expected = "L1 = {// one\n    d1, d2 // two\n    };\n";
+        assert_eq!(fmt(text), expected);
+        assert_eq!(fmt(expected), expected);
+    }
+
+    #[test]
+    fn test_format_unicode_and_errors() {
+        assert_eq!(
+            format("L1 = {} U L1 I {d1};", Notation::Unicode).unwrap(),
+            "L1 = ∅ ∪ L1 ∩ {d1};\n"
+        );
+        assert_eq!(format("L1 = U;", Notation::Ascii).unwrap_err().len(), 1);
+    }
+
+    #[test]
+    fn test_format_example_is_stable() {
+        let text = std::fs::read_to_string("oppg.txt").unwrap();
+        let formatted = fmt(&text);
+        assert_eq!(fmt(&formatted), formatted);
+    }
+}
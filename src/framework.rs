@@ -0,0 +1,702 @@
+//! A monotone framework: equations over a lattice, solved to a fixpoint.
+//!
+//! Every data point has an [`Equation`] whose transfer function computes its
+//! value from the values of the other data points; the equations themselves
+//! say how paths combine. A may analysis starts every data point at the
+//! bottom of the lattice and a must analysis at the top. The direction only
+//! decides which end of the equation list a round starts from. Rounds repeat
+//! until one leaves every value unchanged.
+//!
+//! Monotone equations reach that point within a number of rounds bounded
+//! by the height of the lattice and the number of equations. A system that
+//! is still changing after that never settles, and solving it fails.
+//!
+//! A worklist [`Solver`] instead re-evaluates only the equations that read a
+//! data point whose value changed. Both reach the same fixpoint for monotone
+//! equations.
+
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet, VecDeque};
+
+use crate::diagnostic::Diagnostic;
+use crate::graph::DependencyGraph;
+use crate::names::natural_cmp;
+
+/// A lattice of finite height, which the values of data points live in.
+pub trait Lattice {
+    /// An element of the lattice.
+    type Value: Clone;
+
+    /// The least element.
+    fn bottom(&self) -> Self::Value;
+    /// The greatest element.
+    fn top(&self) -> Self::Value;
+    /// The least upper bound of `a` and `b`.
+    fn join(&self, a: &Self::Value, b: &Self::Value) -> Self::Value;
+    /// The greatest lower bound of `a` and `b`.
+    fn meet(&self, a: &Self::Value, b: &Self::Value) -> Self::Value;
+    /// Whether `a` is below or equal to `b`.
+    fn leq(&self, a: &Self::Value, b: &Self::Value) -> bool;
+    /// The number of steps in the longest chain from bottom to top.
+    fn height(&self) -> usize;
+
+    /// Whether `a` and `b` are the same element.
+    fn equal(&self, a: &Self::Value, b: &Self::Value) -> bool {
+        self.leq(a, b) && self.leq(b, a)
+    }
+}
+
+/// The subsets of a universe, ordered by inclusion.
+#[derive(Debug, Clone, Default)]
+pub struct PowerSet {
+    universe: HashSet<String>,
+}
+
+impl PowerSet {
+    /// The subsets of `universe`.
+    pub fn new(universe: HashSet<String>) -> PowerSet {
+        PowerSet { universe }
+    }
+}
+
+impl Lattice for PowerSet {
+    type Value = HashSet<String>;
+
+    fn bottom(&self) -> HashSet<String> {
+        HashSet::new()
+    }
+
+    fn top(&self) -> HashSet<String> {
+        self.universe.clone()
+    }
+
+    fn join(&self, a: &HashSet<String>, b: &HashSet<String>) -> HashSet<String> {
+        a.union(b).cloned().collect()
+    }
+
+    fn meet(&self, a: &HashSet<String>, b: &HashSet<String>) -> HashSet<String> {
+        a.intersection(b).cloned().collect()
+    }
+
+    fn leq(&self, a: &HashSet<String>, b: &HashSet<String>) -> bool {
+        a.is_subset(b)
+    }
+
+    fn height(&self) -> usize {
+        self.universe.len()
+    }
+}
+
+/// Which end of the equation list the textual orders start from. It does not
+/// change the fixpoint.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Direction {
+    /// The textual orders run from the first equation to the last.
+    Forward,
+    /// The textual orders run from the last equation to the first.
+    Backward,
+}
+
+/// Where data points start, and so which fixpoint is found.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Confluence {
+    /// Data points start at the bottom of the lattice.
+    May,
+    /// Data points start at the top of the lattice.
+    Must,
+}
+
+/// How a [`Framework`] finds the fixpoint.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub enum Solver {
+    /// Evaluates every equation in every round.
+    #[default]
+    RoundRobin,
+    /// Keeps a list of equations to evaluate, starting with all of them, and
+    /// adds the readers of a data point whenever its value changes.
+    Worklist(Strategy),
+}
+
+/// Which equation a worklist solver takes next.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Strategy {
+    /// The one added first.
+    Fifo,
+    /// The one added last.
+    Lifo,
+    /// The one that comes first in the direction of the analysis.
+    Priority,
+}
+
+/// Which values an equation reads during a round.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub enum Mode {
+    /// The newest ones, including those computed earlier in the same round.
+    #[default]
+    GaussSeidel,
+    /// Those of the previous round only, so the order of the equations does
+    /// not matter.
+    Jacobi,
+}
+
+/// The order in which a round evaluates the equations, or in which a
+/// worklist starts and, with [`Strategy::Priority`], takes them.
+///
+/// The graph orders follow the [`DependencyGraph`] and do not depend on the
+/// direction of the analysis.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub enum Order {
+    /// As written.
+    #[default]
+    Textual,
+    /// Last equation first.
+    Reverse,
+    /// An equation before the equations that read it, cycles aside.
+    ReversePostorder,
+    /// An equation after the equations that read it, cycles aside.
+    Postorder,
+    /// Strongly connected components in dependency order, each in textual
+    /// order.
+    Components,
+    /// Shuffled; the same seed gives the same order.
+    Random {
+        /// The seed of the shuffle.
+        seed: u64,
+    },
+}
+
+/// How a [`Framework`] iterates to the fixpoint.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub struct Config {
+    /// Round-robin or worklist.
+    pub solver: Solver,
+    /// Only used by the round-robin solver; a worklist solver always reads
+    /// the newest values.
+    pub mode: Mode,
+    /// The order equations are evaluated in.
+    pub order: Order,
+}
+
+/// The value of every data point.
+pub type State<V> = HashMap<String, V>;
+
+/// Computes the value of one data point from the state.
+pub type Transfer<'a, V> = Box<dyn Fn(&State<V>) -> V + 'a>;
+
+/// The equation of one data point.
+pub struct Equation<'a, V> {
+    /// The data point the equation defines.
+    pub name: String,
+    /// The data points `transfer` reads.
+    pub reads: Vec<String>,
+    /// Computes the value of the data point.
+    pub transfer: Transfer<'a, V>,
+}
+
+impl<'a, V> Equation<'a, V> {
+    /// The equation of `name`, computed by `transfer` from the data points
+    /// in `reads`.
+    pub fn new(
+        name: impl Into<String>,
+        reads: Vec<String>,
+        transfer: impl Fn(&State<V>) -> V + 'a,
+    ) -> Equation<'a, V> {
+        Equation {
+            name: name.into(),
+            reads,
+            transfer: Box::new(transfer),
+        }
+    }
+}
+
+/// The fixpoint of an equation system, with how it was reached.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Solution<V = HashSet<String>> {
+    /// Final value of every data point.
+    pub data_points: State<V>,
+    /// Number of rounds until nothing changed, including the final round.
+    /// A worklist solver has a single round.
+    pub iterations: usize,
+    /// The state after every round; the last one equals `data_points`.
+    pub history: Vec<State<V>>,
+    /// For every data point, the first round after which it kept its final
+    /// value. For a worklist solver, the evaluation after which it did.
+    pub converged: HashMap<String, usize>,
+    /// Number of equations evaluated over all rounds.
+    pub evaluations: usize,
+    /// Number of evaluations that changed the value of their data point.
+    pub updates: usize,
+}
+
+impl Solution {
+    /// The definitions reaching `data_point`, in natural order.
+    pub fn get(&self, data_point: &str) -> Option<Vec<&str>> {
+        let mut points = self
+            .data_points
+            .get(data_point)?
+            .iter()
+            .map(String::as_str)
+            .collect::<Vec<_>>();
+        points.sort_by(|a, b| natural_cmp(a, b));
+        Some(points)
+    }
+}
+
+/// Solves equation systems over a [`Lattice`].
+pub struct Framework<L> {
+    /// The lattice the data points take their values from.
+    pub lattice: L,
+    /// Which end of the equations the textual orders start from.
+    pub direction: Direction,
+    /// Whether data points start at the bottom or the top.
+    pub confluence: Confluence,
+    /// How the fixpoint is found.
+    pub config: Config,
+}
+
+impl<L: Lattice> Framework<L> {
+    /// A framework with the default [`Config`].
+    pub fn new(lattice: L, direction: Direction, confluence: Confluence) -> Framework<L> {
+        Framework {
+            lattice,
+            direction,
+            confluence,
+            config: Config::default(),
+        }
+    }
+
+    /// Replaces the configuration with `config`.
+    pub fn with_config(mut self, config: Config) -> Framework<L> {
+        self.config = config;
+        self
+    }
+
+    /// The value every data point starts from.
+    pub fn initial(&self) -> L::Value {
+        match self.confluence {
+            Confluence::May => self.lattice.bottom(),
+            Confluence::Must => self.lattice.top(),
+        }
+    }
+
+    /// Solves `equations` to their fixpoint, or fails with the data points
+    /// still changing once the solver has run longer than monotone equations
+    /// ever need.
+    pub fn solve(
+        &self,
+        equations: &[Equation<L::Value>],
+    ) -> Result<Solution<L::Value>, Diagnostic> {
+        let graph = DependencyGraph::new(equations.iter().map(|equation| {
+            let reads = equation.reads.iter().map(String::as_str).collect();
+            (equation.name.as_str(), reads)
+        }));
+        let order = self.order(&graph);
+        match self.config.solver {
+            Solver::RoundRobin => self.round_robin(equations, &order),
+            Solver::Worklist(strategy) => self.worklist(equations, &graph, &order, strategy),
+        }
+    }
+
+    /// The equations of `graph` in the configured order.
+    pub fn order(&self, graph: &DependencyGraph) -> Vec<usize> {
+        let textual = |reverse: bool| {
+            let order = 0..graph.len();
+            if reverse == (self.direction == Direction::Forward) {
+                order.rev().collect()
+            } else {
+                order.collect()
+            }
+        };
+        match self.config.order {
+            Order::Textual => textual(false),
+            Order::Reverse => textual(true),
+            Order::ReversePostorder => graph.reverse_postorder(),
+            Order::Postorder => graph.postorder(),
+            Order::Components => graph.components().concat(),
+            Order::Random { seed } => shuffled(graph.len(), seed),
+        }
+    }
+
+    fn initial_state(&self, equations: &[Equation<L::Value>]) -> State<L::Value> {
+        equations
+            .iter()
+            .map(|equation| (equation.name.clone(), self.initial()))
+            .collect()
+    }
+
+    // Evaluates the equations round by round until a round changes nothing.
+    // Every round but the last moves some data point along the lattice,
+    // which a monotone equation does at most `height` times.
+    fn round_robin(
+        &self,
+        equations: &[Equation<L::Value>],
+        order: &[usize],
+    ) -> Result<Solution<L::Value>, Diagnostic> {
+        let limit = (self.lattice.height() + 1) * equations.len();
+        let mut state = self.initial_state(equations);
+        let mut history = Vec::new();
+        let mut evaluations = 0;
+        let mut updates = 0;
+        loop {
+            let previous = state.clone();
+            for &i in order {
+                let equation = &equations[i];
+                let value = match self.config.mode {
+                    Mode::GaussSeidel => (equation.transfer)(&state),
+                    Mode::Jacobi => (equation.transfer)(&previous),
+                };
+                evaluations += 1;
+                if !self.lattice.equal(&state[&equation.name], &value) {
+                    updates += 1;
+                }
+                state.insert(equation.name.clone(), value);
+            }
+            history.push(state.clone());
+            if self.same_state(&state, &previous) {
+                break;
+            }
+            if history.len() > limit {
+                let changing = equations
+                    .iter()
+                    .map(|equation| equation.name.as_str())
+                    .filter(|name| !self.lattice.equal(&state[*name], &previous[*name]));
+                return Err(diverged(changing, format!("{} rounds", history.len())));
+            }
+        }
+
+        Ok(Solution {
+            converged: self.converged(&history, &state),
+            iterations: history.len(),
+            data_points: state,
+            history,
+            evaluations,
+            updates,
+        })
+    }
+
+    // Every update moves a data point along the lattice, and adds at most
+    // every equation back to the worklist.
+    fn worklist(
+        &self,
+        equations: &[Equation<L::Value>],
+        graph: &DependencyGraph,
+        order: &[usize],
+        strategy: Strategy,
+    ) -> Result<Solution<L::Value>, Diagnostic> {
+        let mut rank = vec![0; equations.len()];
+        for (position, &i) in order.iter().enumerate() {
+            rank[i] = position;
+        }
+
+        let mut worklist = Worklist::new(strategy);
+        // A stack takes the first equation first only if it is pushed last.
+        let initial = match strategy {
+            Strategy::Lifo => order.iter().rev().copied().collect::<Vec<_>>(),
+            _ => order.to_vec(),
+        };
+        for &i in &initial {
+            worklist.push(i, rank[i]);
+        }
+        let mut queued = vec![true; equations.len()];
+
+        let limit = (self.lattice.height() + 1) * equations.len() * equations.len();
+        let mut state = self.initial_state(equations);
+        let mut converged = HashMap::new();
+        // The evaluation that last changed each data point.
+        let mut changed = vec![0; equations.len()];
+        let mut evaluations = 0;
+        let mut updates = 0;
+        while let Some(i) = worklist.pop() {
+            if evaluations == limit {
+                // What changed since every equation could have been evaluated
+                // once more.
+                let recent = evaluations.saturating_sub(equations.len());
+                let changing = equations
+                    .iter()
+                    .zip(&changed)
+                    .filter(|(_, &evaluation)| evaluation > recent)
+                    .map(|(equation, _)| equation.name.as_str());
+                return Err(diverged(changing, format!("{} evaluations", limit)));
+            }
+            queued[i] = false;
+            let equation = &equations[i];
+            let value = (equation.transfer)(&state);
+            evaluations += 1;
+            if self.lattice.equal(&state[&equation.name], &value) {
+                converged
+                    .entry(equation.name.clone())
+                    .or_insert(evaluations);
+                continue;
+            }
+            updates += 1;
+            changed[i] = evaluations;
+            converged.insert(equation.name.clone(), evaluations);
+            state.insert(equation.name.clone(), value);
+            for &reader in graph.readers(i) {
+                if !queued[reader] {
+                    queued[reader] = true;
+                    worklist.push(reader, rank[reader]);
+                }
+            }
+        }
+
+        Ok(Solution {
+            converged,
+            iterations: 1,
+            history: vec![state.clone()],
+            data_points: state,
+            evaluations,
+            updates,
+        })
+    }
+
+    fn same_state(&self, a: &State<L::Value>, b: &State<L::Value>) -> bool {
+        a.iter().all(|(name, value)| {
+            b.get(name)
+                .is_some_and(|other| self.lattice.equal(value, other))
+        })
+    }
+
+    fn converged(
+        &self,
+        history: &[State<L::Value>],
+        last: &State<L::Value>,
+    ) -> HashMap<String, usize> {
+        let mut converged = HashMap::new();
+        for (name, value) in last {
+            let changed_after = history
+                .iter()
+                .rposition(|state| !self.lattice.equal(&state[name], value))
+                .map_or(0, |round| round + 1);
+            converged.insert(name.clone(), changed_after + 1);
+        }
+        converged
+    }
+}
+
+// The error for a system whose `changing` data points were still changing
+// after `limit`.
+fn diverged<'a>(changing: impl Iterator<Item = &'a str>, limit: String) -> Diagnostic {
+    let changing = changing
+        .map(|name| format!("`{}`", name))
+        .collect::<Vec<_>>();
+    Diagnostic::error(format!(
+        "no fixpoint after {}: {} still changing",
+        limit,
+        changing.join(", ")
+    ))
+}
+
+// A permutation of `0..len` by a Fisher-Yates shuffle driven by SplitMix64.
+fn shuffled(len: usize, seed: u64) -> Vec<usize> {
+    let mut state = seed;
+    let mut next = || {
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    };
+    let mut order = (0..len).collect::<Vec<_>>();
+    for i in (1..len).rev() {
+        let j = (next() % (i as u64 + 1)) as usize;
+        order.swap(i, j);
+    }
+    order
+}
+
+enum Worklist {
+    Queue(VecDeque<usize>),
+    Stack(Vec<usize>),
+    // Lowest rank first.
+    Heap(BinaryHeap<Reverse<(usize, usize)>>),
+}
+
+impl Worklist {
+    fn new(strategy: Strategy) -> Worklist {
+        match strategy {
+            Strategy::Fifo => Worklist::Queue(VecDeque::new()),
+            Strategy::Lifo => Worklist::Stack(Vec::new()),
+            Strategy::Priority => Worklist::Heap(BinaryHeap::new()),
+        }
+    }
+
+    fn push(&mut self, equation: usize, rank: usize) {
+        match self {
+            Worklist::Queue(queue) => queue.push_back(equation),
+            Worklist::Stack(stack) => stack.push(equation),
+            Worklist::Heap(heap) => heap.push(Reverse((rank, equation))),
+        }
+    }
+
+    fn pop(&mut self) -> Option<usize> {
+        match self {
+            Worklist::Queue(queue) => queue.pop_front(),
+            Worklist::Stack(stack) => stack.pop(),
+            Worklist::Heap(heap) => heap.pop().map(|Reverse((_, equation))| equation),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set(names: &[&str]) -> HashSet<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    const STRATEGIES: [Strategy; 3] = [Strategy::Fifo, Strategy::Lifo, Strategy::Priority];
+
+    #[test]
+    fn test_power_set_lattice() {
+        let lattice = PowerSet::new(set(&["d1", "d2"]));
+        let (a, b) = (set(&["d1"]), set(&["d2"]));
+        assert_eq!(lattice.join(&a, &b), lattice.top());
+        assert_eq!(lattice.meet(&a, &b), lattice.bottom());
+        assert!(lattice.leq(&lattice.bottom(), &a));
+        assert!(!lattice.leq(&a, &b));
+        assert!(lattice.equal(&lattice.join(&a, &a), &a));
+    }
+
+    // A must analysis starts at the top and only shrinks; a round that
+    // removes definitions is a change.
+    #[test]
+    fn test_must_analysis_shrinks() {
+        let lattice = PowerSet::new(set(&["e1", "e2"]));
+        let framework = Framework::new(lattice.clone(), Direction::Backward, Confluence::Must);
+        let equations = vec![
+            Equation::new("L1", names(&["L2"]), |state: &State<HashSet<String>>| {
+                state["L2"].intersection(&set(&["e1"])).cloned().collect()
+            }),
+            Equation::new("L2", names(&[]), |_: &State<HashSet<String>>| {
+                set(&["e1", "e2"])
+            }),
+            Equation::new("L3", names(&["L1"]), |state: &State<HashSet<String>>| {
+                state["L1"].intersection(&set(&["e2"])).cloned().collect()
+            }),
+        ];
+        let solution = framework.solve(&equations).unwrap();
+        assert_eq!(solution.get("L1"), Some(vec!["e1"]));
+        assert_eq!(solution.get("L3"), Some(vec![]));
+        assert_eq!(solution.iterations, 3);
+        assert_eq!(solution.converged["L3"], 2);
+
+        for strategy in STRATEGIES {
+            let config = Config {
+                solver: Solver::Worklist(strategy),
+                ..Config::default()
+            };
+            let framework = Framework::new(lattice.clone(), Direction::Backward, Confluence::Must);
+            let worklist = framework.with_config(config).solve(&equations).unwrap();
+            assert_eq!(worklist.data_points, solution.data_points);
+            assert!(worklist.evaluations < solution.evaluations);
+        }
+    }
+
+    // In a Jacobi round `L2` still sees the `L1` of the round before.
+    #[test]
+    fn test_jacobi_rounds() {
+        let lattice = PowerSet::new(set(&["d1"]));
+        let equations = vec![
+            Equation::new("L1", names(&[]), |_: &State<HashSet<String>>| set(&["d1"])),
+            Equation::new("L2", names(&["L1"]), |state: &State<HashSet<String>>| {
+                state["L1"].clone()
+            }),
+        ];
+        let framework = Framework::new(lattice.clone(), Direction::Forward, Confluence::May);
+        let gauss_seidel = framework.solve(&equations).unwrap();
+        let config = Config {
+            mode: Mode::Jacobi,
+            ..Config::default()
+        };
+        let framework = Framework::new(lattice, Direction::Forward, Confluence::May);
+        let jacobi = framework.with_config(config).solve(&equations).unwrap();
+
+        assert_eq!(jacobi.data_points, gauss_seidel.data_points);
+        assert_eq!(gauss_seidel.iterations, 2);
+        assert_eq!(jacobi.iterations, 3);
+        assert_eq!(jacobi.history[0].get("L2"), Some(&set(&[])));
+        assert_eq!(jacobi.converged["L2"], 2);
+    }
+
+    // `L1 = {d1} / L1` is `{d1}` when `L1` is empty and empty otherwise, so
+    // neither solver ever settles. `L2` settles, but keeps being evaluated.
+    #[test]
+    fn test_oscillation_fails() {
+        let equations = vec![
+            Equation::new("L1", names(&["L1"]), |state: &State<HashSet<String>>| {
+                set(&["d1"]).difference(&state["L1"]).cloned().collect()
+            }),
+            Equation::new("L2", names(&["L1"]), |state: &State<HashSet<String>>| {
+                state["L1"].union(&set(&["d1"])).cloned().collect()
+            }),
+        ];
+        let framework = || {
+            Framework::new(
+                PowerSet::new(set(&["d1"])),
+                Direction::Forward,
+                Confluence::May,
+            )
+        };
+        let diagnostic = framework().solve(&equations).unwrap_err();
+        assert_eq!(
+            diagnostic.message,
+            "no fixpoint after 5 rounds: `L1` still changing"
+        );
+
+        for strategy in STRATEGIES {
+            let config = Config {
+                solver: Solver::Worklist(strategy),
+                ..Config::default()
+            };
+            let diagnostic = framework()
+                .with_config(config)
+                .solve(&equations)
+                .unwrap_err();
+            assert_eq!(
+                diagnostic.message,
+                "no fixpoint after 8 evaluations: `L1` still changing"
+            );
+        }
+    }
+
+    #[test]
+    fn test_orders() {
+        let equations = vec![
+            Equation::new("L1", names(&[]), |_: &State<HashSet<String>>| set(&["d1"])),
+            Equation::new("L2", names(&["L3"]), |state: &State<HashSet<String>>| {
+                state["L3"].clone()
+            }),
+            Equation::new("L3", names(&["L1"]), |state: &State<HashSet<String>>| {
+                state["L1"].clone()
+            }),
+        ];
+        let solve = |direction, order| {
+            let config = Config {
+                order,
+                ..Config::default()
+            };
+            let framework = Framework::new(PowerSet::new(set(&["d1"])), direction, Confluence::May);
+            framework.with_config(config).solve(&equations).unwrap()
+        };
+        let textual = solve(Direction::Forward, Order::Textual);
+        let rpo = solve(Direction::Forward, Order::ReversePostorder);
+        assert_eq!(textual.iterations, 3);
+        assert_eq!(rpo.iterations, 2);
+        assert_eq!(rpo.data_points, textual.data_points);
+        assert_eq!(solve(Direction::Backward, Order::Reverse).iterations, 3);
+        assert_eq!(solve(Direction::Forward, Order::Components).iterations, 2);
+
+        let random = |seed| shuffled(10, seed);
+        assert_eq!(random(7), random(7));
+        assert_ne!(random(7), random(8));
+        let mut sorted = random(7);
+        sorted.sort();
+        assert_eq!(sorted, (0..10).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,252 @@
+//! The dependency graph of an equation system: which equations read the data
+//! point of which others.
+//!
+//! Equations are numbered by their position. Names that are not the data
+//! point of an equation, such as constants, are not part of the graph.
+
+use std::collections::HashMap;
+
+use crate::ast;
+
+/// Which equations read the data point of which others.
+#[derive(Debug, Clone)]
+pub struct DependencyGraph {
+    names: Vec<String>,
+    // `reads[i]` are the equations whose data points equation `i` reads.
+    reads: Vec<Vec<usize>>,
+    // `readers[i]` are the equations that read the data point of equation `i`.
+    readers: Vec<Vec<usize>>,
+}
+
+impl DependencyGraph {
+    /// Builds the graph from every equation's data point and the names its
+    /// right-hand side refers to.
+    pub fn new<'a>(
+        equations: impl IntoIterator<Item = (&'a str, Vec<&'a str>)>,
+    ) -> DependencyGraph {
+        let equations = equations.into_iter().collect::<Vec<_>>();
+        let mut index = HashMap::new();
+        for (i, (name, _)) in equations.iter().enumerate() {
+            index.entry(*name).or_insert(i);
+        }
+
+        let mut reads = vec![Vec::new(); equations.len()];
+        let mut readers = vec![Vec::new(); equations.len()];
+        for (i, (_, names)) in equations.iter().enumerate() {
+            for name in names {
+                let Some(&j) = index.get(name) else {
+                    continue;
+                };
+                if !reads[i].contains(&j) {
+                    reads[i].push(j);
+                    readers[j].push(i);
+                }
+            }
+        }
+
+        DependencyGraph {
+            names: equations.iter().map(|(name, _)| name.to_string()).collect(),
+            reads,
+            readers,
+        }
+    }
+
+    /// The graph of the equations of `spec`.
+    pub fn from_spec(spec: &ast::Spec) -> DependencyGraph {
+        DependencyGraph::new(spec.equations.iter().map(|equation| {
+            let names = equation.rhs.vars().into_iter().map(|var| var.name.as_str());
+            (equation.lhs.name.as_str(), names.collect())
+        }))
+    }
+
+    /// Number of equations.
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    /// Whether there are no equations.
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    /// The data point of equation `i`.
+    pub fn name(&self, i: usize) -> &str {
+        &self.names[i]
+    }
+
+    /// The equations whose data points equation `i` reads.
+    pub fn reads(&self, i: usize) -> &[usize] {
+        &self.reads[i]
+    }
+
+    /// The equations that read the data point of equation `i`.
+    pub fn readers(&self, i: usize) -> &[usize] {
+        &self.readers[i]
+    }
+
+    /// The equations in the postorder of a depth-first search along the data
+    /// flow. The search starts from the equations that read no data point,
+    /// then from any equation left, in textual order. Unless they are in a
+    /// cycle, an equation comes after the equations that read it.
+    pub fn postorder(&self) -> Vec<usize> {
+        let mut visited = vec![false; self.len()];
+        let mut order = Vec::new();
+        // The search path, each equation with the next of its readers to
+        // visit.
+        let mut path = Vec::new();
+        let entries = (0..self.len()).filter(|&i| self.reads[i].is_empty());
+        for start in entries.chain(0..self.len()) {
+            if visited[start] {
+                continue;
+            }
+            visited[start] = true;
+            path.push((start, 0));
+            while let Some((i, next)) = path.last_mut() {
+                let i = *i;
+                match self.readers[i].get(*next) {
+                    Some(&reader) => {
+                        *next += 1;
+                        if !visited[reader] {
+                            visited[reader] = true;
+                            path.push((reader, 0));
+                        }
+                    }
+                    None => {
+                        path.pop();
+                        order.push(i);
+                    }
+                }
+            }
+        }
+        order
+    }
+
+    /// Unless they are in a cycle, an equation comes before the equations
+    /// that read it.
+    pub fn reverse_postorder(&self) -> Vec<usize> {
+        let mut order = self.postorder();
+        order.reverse();
+        order
+    }
+
+    /// The strongly connected components, each in textual order. A component
+    /// comes after the components it reads from.
+    pub fn components(&self) -> Vec<Vec<usize>> {
+        let mut tarjan = Tarjan {
+            graph: self,
+            index: vec![None; self.len()],
+            low: vec![0; self.len()],
+            stack: Vec::new(),
+            on_stack: vec![false; self.len()],
+            next: 0,
+            components: Vec::new(),
+        };
+        for i in 0..self.len() {
+            if tarjan.index[i].is_none() {
+                tarjan.connect(i);
+            }
+        }
+        tarjan.components
+    }
+}
+
+// Tarjan's algorithm along the `reads` edges, which finishes a component
+// only after every component it reads from.
+struct Tarjan<'a> {
+    graph: &'a DependencyGraph,
+    index: Vec<Option<usize>>,
+    low: Vec<usize>,
+    stack: Vec<usize>,
+    on_stack: Vec<bool>,
+    next: usize,
+    components: Vec<Vec<usize>>,
+}
+
+impl Tarjan<'_> {
+    fn connect(&mut self, root: usize) {
+        // The search path, each equation with the next of its reads to
+        // follow.
+        let mut path = vec![(root, 0)];
+        self.open(root);
+        while let Some((i, next)) = path.last_mut() {
+            let i = *i;
+            if let Some(&j) = self.graph.reads(i).get(*next) {
+                *next += 1;
+                match self.index[j] {
+                    None => {
+                        self.open(j);
+                        path.push((j, 0));
+                    }
+                    Some(index) if self.on_stack[j] => self.low[i] = self.low[i].min(index),
+                    Some(_) => {}
+                }
+                continue;
+            }
+
+            path.pop();
+            if let Some(&(parent, _)) = path.last() {
+                self.low[parent] = self.low[parent].min(self.low[i]);
+            }
+            if Some(self.low[i]) == self.index[i] {
+                let mut component = Vec::new();
+                while let Some(j) = self.stack.pop() {
+                    self.on_stack[j] = false;
+                    component.push(j);
+                    if j == i {
+                        break;
+                    }
+                }
+                component.sort();
+                self.components.push(component);
+            }
+        }
+    }
+
+    fn open(&mut self, i: usize) {
+        self.index[i] = Some(self.next);
+        self.low[i] = self.next;
+        self.next += 1;
+        self.stack.push(i);
+        self.on_stack[i] = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_graph_from_spec() {
+        let text = "let K = {d1};\nL1 = K U L3;\nL2 = L1 U L1;\nL3 = L2 / K;";
+        let spec = crate::parse_spec(text).unwrap();
+        let graph = DependencyGraph::from_spec(spec.ast());
+        assert_eq!(graph.len(), 3);
+        assert_eq!(graph.name(2), "L3");
+        assert_eq!(graph.reads(0), &[2]);
+        assert_eq!(graph.reads(1), &[0]);
+        assert_eq!(graph.readers(0), &[1]);
+        assert_eq!(graph.readers(2), &[0]);
+    }
+
+    #[test]
+    fn test_graph_orders() {
+        // L1 -> L2 -> L3 -> L4, with L4 feeding back into L2.
+        let text = "L3 = L2;\nL1 = {d1};\nL4 = L3;\nL2 = L1 U L4;";
+        let spec = crate::parse_spec(text).unwrap();
+        let graph = DependencyGraph::from_spec(spec.ast());
+        let names = |order: Vec<usize>| {
+            order
+                .into_iter()
+                .map(|i| graph.name(i).to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names(graph.reverse_postorder()), ["L1", "L2", "L3", "L4"]);
+        assert_eq!(names(graph.postorder()), ["L4", "L3", "L2", "L1"]);
+        let components = graph
+            .components()
+            .into_iter()
+            .map(names)
+            .collect::<Vec<_>>();
+        assert_eq!(components, [vec!["L1"], vec!["L3", "L4", "L2"]]);
+    }
+}
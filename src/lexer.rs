@@ -1,161 +1,606 @@
+//! Splits specification text into tokens.
+
+use crate::diagnostic::Diagnostic;
+use crate::span::{FileId, Span};
+
+/// The kinds of token.
 #[derive(Debug, PartialEq, Clone)]
 pub enum TokenType {
-    Unkown,
-    SetOpen,       // {
-    SetClose,      // }
-    DataPoint,     // L
-    Definition,    // d
-    Equals,        // =
-    Union,         // U
-    SetDifference, // \
-    Comma,         // ,
-    NewLine,       // \n
-    Eof,           // EOF
+    /// `{`
+    SetOpen,
+    /// `}`
+    SetClose,
+    /// `∅`
+    EmptySet,
+    /// `(`
+    ParenOpen,
+    /// `)`
+    ParenClose,
+    /// A name, e.g. `L1`, `d1`, `IN_B3`, `RD_entry(4)` or `x@7`.
+    Identifier,
+    /// `=`
+    Equals,
+    /// `⊆` or `⊇`.
+    Subset,
+    /// `let`
+    Let,
+    /// `U` or `∪`.
+    Union,
+    /// `I`, `∩` or `&`.
+    Intersection,
+    /// `/` or `∖`.
+    SetDifference,
+    /// `,`
+    Comma,
+    /// `..`
+    Range,
+    /// `~`
+    Complement,
+    /// `universe`
+    UniverseKw,
+    /// `include`
+    Include,
+    /// A quoted path, e.g. `"shared.txt"`.
+    String,
+    /// A line break.
+    NewLine,
+    /// The end of the text.
+    Eof,
+}
+
+impl TokenType {
+    /// How the token type is named in diagnostics.
+    pub fn describe(&self) -> &'static str {
+        match self {
+            TokenType::SetOpen => "`{`",
+            TokenType::SetClose => "`}`",
+            TokenType::EmptySet => "`∅`",
+            TokenType::ParenOpen => "`(`",
+            TokenType::ParenClose => "`)`",
+            TokenType::Identifier => "name",
+            TokenType::Equals => "`=`",
+            TokenType::Subset => "`⊆`",
+            TokenType::Let => "`let`",
+            TokenType::Union => "`U`",
+            TokenType::Intersection => "`I`",
+            TokenType::SetDifference => "`/`",
+            TokenType::Comma => "`,`",
+            TokenType::Range => "`..`",
+            TokenType::Complement => "`~`",
+            TokenType::UniverseKw => "`universe`",
+            TokenType::Include => "`include`",
+            TokenType::String => "string",
+            TokenType::NewLine => "`;`",
+            TokenType::Eof => "end of file",
+        }
+    }
 }
 
+/// A token and where it is.
 #[derive(Debug, Clone)]
 pub struct Token {
+    /// What kind of token it is.
     pub token_type: TokenType,
+    /// The text of the token.
     pub lexeme: String,
+    /// Where the token is.
+    pub span: Span,
+    /// Whether only whitespace and comments separate the token from the start
+    /// of its line. The first token of the text starts a line.
+    pub starts_line: bool,
+}
+
+impl Token {
+    /// How this particular token is named in diagnostics.
+    pub fn describe(&self) -> String {
+        match self.token_type {
+            TokenType::Eof => self.token_type.describe().to_string(),
+            _ => format!("`{}`", self.lexeme),
+        }
+    }
+
+    /// The contents of a `String` token, without the quotes.
+    pub fn string_value(&self) -> &str {
+        let inner = self.lexeme.strip_prefix('"').unwrap_or(&self.lexeme);
+        inner.strip_suffix('"').unwrap_or(inner)
+    }
 }
 
+/// Turns text into [`Token`]s.
 pub struct Lexer {
     text: String,
+    file: FileId,
     cursor: usize,
+    line: usize,
+    line_start: usize,
+    starts_line: bool,
+    diagnostics: Vec<Diagnostic>,
 }
 
 impl Lexer {
+    /// A lexer for a text that is not part of a source map.
     pub fn new(text: String) -> Lexer {
-        Lexer { text, cursor: 0 }
+        Lexer::in_file(text, 0)
+    }
+
+    /// A lexer whose spans point into `file` of a source map.
+    pub fn in_file(text: String, file: FileId) -> Lexer {
+        Lexer {
+            text,
+            file,
+            cursor: 0,
+            line: 1,
+            line_start: 0,
+            starts_line: true,
+            diagnostics: Vec::new(),
+        }
     }
 
+    /// Invalid characters found so far. The lexer skips them, or lexes the
+    /// token they were most likely meant to be.
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    // The cursor is always on a char boundary.
     fn current(&self) -> char {
-        self.text
-            .get(self.cursor..self.cursor + 1)
-            .unwrap()
-            .chars()
-            .next()
-            .unwrap()
+        self.text[self.cursor..].chars().next().unwrap()
+    }
+
+    fn advance(&mut self) {
+        let c = self.current();
+        self.cursor += c.len_utf8();
+        if c == '\n' {
+            self.line += 1;
+            self.line_start = self.cursor;
+        }
     }
 
+    fn position(&self) -> Span {
+        Span {
+            start: self.cursor,
+            end: self.cursor,
+            line: self.line,
+            column: self.text[self.line_start..self.cursor].chars().count() + 1,
+            file: self.file,
+        }
+    }
+
+    fn token(&mut self, token_type: TokenType, start: Span) -> Token {
+        let span = Span {
+            end: self.cursor,
+            ..start
+        };
+        let starts_line = self.starts_line;
+        self.starts_line = false;
+        Token {
+            token_type,
+            lexeme: self.text[span.start..span.end].to_string(),
+            span,
+            starts_line,
+        }
+    }
+
+    /// Lexes the whole text. The last token is always `Eof`.
     pub fn lex_all(&mut self) -> Vec<Token> {
         let mut tokens = Vec::new();
-        while self.cursor < self.text.len() {
+        loop {
             let token = self.lex();
+            let done = token.token_type == TokenType::Eof;
             tokens.push(token);
+            if done {
+                break;
+            }
         }
         tokens
     }
 
     fn lex(&mut self) -> Token {
-        if self.cursor >= self.text.len() {
-            return Token {
-                token_type: TokenType::Eof,
-                lexeme: "EOF".to_string(),
-            };
-        }
+        loop {
+            self.skip_whitespace();
+
+            let start = self.position();
+            if self.cursor >= self.text.len() {
+                return Token {
+                    token_type: TokenType::Eof,
+                    lexeme: "EOF".to_string(),
+                    span: start,
+                    starts_line: self.starts_line,
+                };
+            }
 
-        if self.current() == ';' {
-            self.cursor += 1;
-            return Token {
-                token_type: TokenType::NewLine,
-                lexeme: ";".to_string(),
+            let token_type = match self.current() {
+                ';' => TokenType::NewLine,
+                '{' => TokenType::SetOpen,
+                '}' => TokenType::SetClose,
+                '=' => TokenType::Equals,
+                '∅' => TokenType::EmptySet,
+                '(' => TokenType::ParenOpen,
+                ')' => TokenType::ParenClose,
+                '∪' => TokenType::Union,
+                '∩' | '&' => TokenType::Intersection,
+                '/' | '∖' => TokenType::SetDifference,
+                ',' => TokenType::Comma,
+                '~' => TokenType::Complement,
+                '"' => return self.lex_string(start),
+                '.' if self.text[self.cursor..].starts_with("..") => {
+                    self.advance();
+                    TokenType::Range
+                }
+                c if is_identifier_start(c) => return self.lex_identifier(start),
+                '\\' => {
+                    self.advance();
+                    return self.mistaken_operator(
+                        TokenType::SetDifference,
+                        start,
+                        "set difference is written `/`",
+                    );
+                }
+                '⊆' | '⊇' => TokenType::Subset,
+                c => {
+                    self.advance();
+                    let span = Span {
+                        end: self.cursor,
+                        ..start
+                    };
+                    self.diagnostics.push(
+                        Diagnostic::error(format!("unknown character `{}`", c))
+                            .with_label(span, "not valid in a specification"),
+                    );
+                    continue;
+                }
             };
+            self.advance();
+            return self.token(token_type, start);
         }
+    }
 
-        self.skip_whitespace();
+    // `\` or `u`: reported, then lexed as the operator it was meant to be.
+    fn mistaken_operator(&mut self, token_type: TokenType, start: Span, help: &str) -> Token {
+        let token = self.token(token_type, start);
+        self.diagnostics.push(
+            Diagnostic::error(format!("unknown character `{}`", token.lexeme))
+                .with_label(
+                    token.span,
+                    format!("did you mean {}?", token.token_type.describe()),
+                )
+                .with_help(help),
+        );
+        token
+    }
 
-        if self.cursor >= self.text.len() {
-            return Token {
-                token_type: TokenType::Eof,
-                lexeme: "EOF".to_string(),
-            };
+    // A name, the `U` and `I` operators or a keyword. A name may end in a parenthesised
+    // suffix such as `RD_entry(4)` as long as the `(` follows without a space.
+    fn lex_identifier(&mut self, start: Span) -> Token {
+        while self.cursor < self.text.len() && is_identifier_continue(self.current()) {
+            self.advance();
         }
 
-        if self.current() == '{' {
-            self.cursor += 1;
-            return Token {
-                token_type: TokenType::SetOpen,
-                lexeme: "{".to_string(),
-            };
+        match &self.text[start.start..self.cursor] {
+            "U" => return self.token(TokenType::Union, start),
+            "I" => return self.token(TokenType::Intersection, start),
+            "let" => return self.token(TokenType::Let, start),
+            "universe" => return self.token(TokenType::UniverseKw, start),
+            "include" => return self.token(TokenType::Include, start),
+            // `u` stays reserved so that a lowercase union gets a helpful error.
+            "u" => {
+                return self.mistaken_operator(
+                    TokenType::Union,
+                    start,
+                    "union is written with an uppercase `U`",
+                )
+            }
+            _ => {}
         }
 
-        if self.current() == '}' {
-            self.cursor += 1;
-            return Token {
-                token_type: TokenType::SetClose,
-                lexeme: "}".to_string(),
-            };
+        if self.text[self.cursor..].starts_with('(') {
+            let suffix = self.text[self.cursor + 1..]
+                .find(|c: char| !is_identifier_continue(c))
+                .map(|i| self.cursor + 1 + i);
+            if let Some(close) = suffix.filter(|&i| self.text[i..].starts_with(')')) {
+                while self.cursor <= close {
+                    self.advance();
+                }
+            }
         }
+        self.token(TokenType::Identifier, start)
+    }
 
-        if self.current() == 'L' {
-            self.cursor += 1;
-            return Token {
-                token_type: TokenType::DataPoint,
-                lexeme: "L".to_string() + &self.lex_number(),
-            };
+    // A double-quoted string on a single line. There are no escapes.
+    fn lex_string(&mut self, start: Span) -> Token {
+        self.advance();
+        while self.cursor < self.text.len() && !matches!(self.current(), '"' | '\n') {
+            self.advance();
         }
 
-        if self.current() == 'd' {
-            self.cursor += 1;
-            return Token {
-                token_type: TokenType::Definition,
-                lexeme: "d".to_string() + &self.lex_number(),
+        if self.cursor < self.text.len() && self.current() == '"' {
+            self.advance();
+        } else {
+            let span = Span {
+                end: start.start + 1,
+                ..start
             };
+            self.diagnostics.push(
+                Diagnostic::error("unterminated string")
+                    .with_label(span, "string starts here")
+                    .with_help("close the string with `\"` on the same line"),
+            );
         }
+        self.token(TokenType::String, start)
+    }
 
-        if self.current() == '=' {
-            self.cursor += 1;
-            return Token {
-                token_type: TokenType::Equals,
-                lexeme: "=".to_string(),
-            };
+    // Skips whitespace, `// line` comments and `/* block */` comments.
+    fn skip_whitespace(&mut self) {
+        while self.cursor < self.text.len() {
+            let rest = &self.text[self.cursor..];
+            if rest.starts_with("//") {
+                while self.cursor < self.text.len() && self.current() != '\n' {
+                    self.advance();
+                }
+            } else if rest.starts_with("/*") {
+                self.skip_block_comment();
+            } else if self.current().is_whitespace() {
+                if self.current() == '\n' {
+                    self.starts_line = true;
+                }
+                self.advance();
+            } else {
+                break;
+            }
         }
+    }
 
-        if self.current() == 'U' {
-            self.cursor += 1;
-            return Token {
-                token_type: TokenType::Union,
-                lexeme: "U".to_string(),
-            };
+    fn skip_block_comment(&mut self) {
+        let start = self.position();
+        self.advance();
+        self.advance();
+        while self.cursor < self.text.len() && !self.text[self.cursor..].starts_with("*/") {
+            if self.current() == '\n' {
+                self.starts_line = true;
+            }
+            self.advance();
         }
 
-        if self.current() == '/' {
-            self.cursor += 1;
-            return Token {
-                token_type: TokenType::SetDifference,
-                lexeme: "/".to_string(),
+        if self.cursor >= self.text.len() {
+            let span = Span {
+                end: start.start + 2,
+                ..start
             };
+            self.diagnostics.push(
+                Diagnostic::error("unterminated block comment")
+                    .with_label(span, "comment starts here")
+                    .with_help("close the comment with `*/`"),
+            );
+            return;
         }
+        self.advance();
+        self.advance();
+    }
+}
 
-        if self.current() == ',' {
-            self.cursor += 1;
-            return Token {
-                token_type: TokenType::Comma,
-                lexeme: ",".to_string(),
-            };
-        }
+fn is_identifier_start(c: char) -> bool {
+    c.is_alphabetic() || c == '_'
+}
 
-        let token = Token {
-            token_type: TokenType::Unkown,
-            lexeme: self.current().to_string(),
-        };
-        self.cursor += 1;
-        return token;
+fn is_identifier_continue(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '@' || c == '\''
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lex_spans() {
+        let mut lexer = Lexer::new("L1 = {};\nL2 = L1 U {d1};".to_string());
+        let tokens = lexer.lex_all();
+        let union = tokens
+            .iter()
+            .find(|token| token.token_type == TokenType::Union)
+            .unwrap();
+        assert_eq!(union.span.start, 17);
+        assert_eq!(union.span.len(), 1);
+        assert_eq!((union.span.line, union.span.column), (2, 9));
+        let eof = tokens.last().unwrap();
+        assert_eq!(eof.token_type, TokenType::Eof);
+        assert_eq!((eof.span.line, eof.span.column), (2, 16));
     }
 
-    fn lex_number(&mut self) -> String {
-        let mut number = String::new();
-        while self.cursor < self.text.len() && self.current().is_ascii_digit() {
-            number.push(self.current() as char);
-            self.cursor += 1;
-        }
-        number
+    #[test]
+    fn test_lex_whitespace_before_semicolon() {
+        let mut lexer = Lexer::new("L1 = {} ;\n".to_string());
+        let tokens = lexer.lex_all();
+        let types = tokens
+            .iter()
+            .map(|t| t.token_type.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            types,
+            vec![
+                TokenType::Identifier,
+                TokenType::Equals,
+                TokenType::SetOpen,
+                TokenType::SetClose,
+                TokenType::NewLine,
+                TokenType::Eof
+            ]
+        );
     }
 
-    fn skip_whitespace(&mut self) {
-        while self.cursor < self.text.len() && self.current().is_ascii_whitespace() {
-            self.cursor += 1;
-        }
+    #[test]
+    fn test_lex_unknown_character() {
+        let mut lexer = Lexer::new("L1 = {d1} # {d2};".to_string());
+        let tokens = lexer.lex_all();
+        assert_eq!(tokens.len(), 10);
+        let diagnostic = &lexer.diagnostics()[0];
+        assert_eq!(diagnostic.message, "unknown character `#`");
+        assert_eq!(diagnostic.span.unwrap().column, 11);
+        assert!(diagnostic.help.is_none());
+    }
+
+    #[test]
+    fn test_lex_suggestions() {
+        let mut lexer = Lexer::new("L2 = L1 \\ {d1} u {d2};".to_string());
+        let tokens = lexer.lex_all();
+        let lexemes = tokens.iter().map(|t| t.lexeme.as_str()).collect::<Vec<_>>();
+        assert_eq!(
+            lexemes,
+            vec!["L2", "=", "L1", "\\", "{", "d1", "}", "u", "{", "d2", "}", ";", "EOF"]
+        );
+        assert_eq!(tokens[3].token_type, TokenType::SetDifference);
+        assert_eq!(tokens[7].token_type, TokenType::Union);
+
+        let help = lexer
+            .diagnostics()
+            .iter()
+            .map(|d| d.help.clone().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            help,
+            vec![
+                "set difference is written `/`",
+                "union is written with an uppercase `U`",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_lex_unicode_notation() {
+        let mut lexer = Lexer::new("L2 = L1 ∖ {d1} ∪ ∅;".to_string());
+        let tokens = lexer.lex_all();
+        let types = tokens
+            .iter()
+            .map(|t| t.token_type.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            types,
+            vec![
+                TokenType::Identifier,
+                TokenType::Equals,
+                TokenType::Identifier,
+                TokenType::SetDifference,
+                TokenType::SetOpen,
+                TokenType::Identifier,
+                TokenType::SetClose,
+                TokenType::Union,
+                TokenType::EmptySet,
+                TokenType::NewLine,
+                TokenType::Eof
+            ]
+        );
+        assert!(lexer.diagnostics().is_empty());
+        assert_eq!(tokens[8].span.len(), '∅'.len_utf8());
+        assert_eq!(tokens[8].span.column, 18);
+    }
+
+    #[test]
+    fn test_lex_multibyte_unknown_character() {
+        let mut lexer = Lexer::new("L1 = {d1} ⊆ §;".to_string());
+        let tokens = lexer.lex_all();
+        assert_eq!(tokens.len(), 8);
+        let messages = lexer
+            .diagnostics()
+            .iter()
+            .map(|d| d.message.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(messages, vec!["unknown character `§`"]);
+        assert_eq!(tokens[5].token_type, TokenType::Subset);
+    }
+
+    #[test]
+    fn test_lex_identifiers() {
+        let mut lexer = Lexer::new("IN_B3 = RD_entry(4) U Lfoo U x@7 U(U2) I(L) (1);".to_string());
+        let tokens = lexer.lex_all();
+        let identifiers = tokens
+            .iter()
+            .filter(|t| t.token_type == TokenType::Identifier)
+            .map(|t| t.lexeme.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            identifiers,
+            vec!["IN_B3", "RD_entry(4)", "Lfoo", "x@7", "U2", "L"]
+        );
+        assert_eq!(lexer.diagnostics().len(), 1);
+        assert_eq!(tokens[7].token_type, TokenType::Union);
+        assert_eq!(tokens[8].token_type, TokenType::ParenOpen);
+    }
+
+    #[test]
+    fn test_lex_intersection() {
+        let mut lexer = Lexer::new("L3 = L1 ∩ L2 I L4 & L5 U In;".to_string());
+        let tokens = lexer.lex_all();
+        let intersections = tokens
+            .iter()
+            .filter(|t| t.token_type == TokenType::Intersection)
+            .count();
+        assert_eq!(intersections, 3);
+        assert_eq!(tokens[10].token_type, TokenType::Identifier);
+    }
+
+    #[test]
+    fn test_lex_comments() {
+        let text = "// block B1\nL1 = {d1}; /* entry */ L2 = L1 // loop\n  /* a\n b */ U L2;";
+        let mut lexer = Lexer::new(text.to_string());
+        let tokens = lexer.lex_all();
+        let lexemes = tokens.iter().map(|t| t.lexeme.as_str()).collect::<Vec<_>>();
+        assert_eq!(
+            lexemes,
+            vec!["L1", "=", "{", "d1", "}", ";", "L2", "=", "L1", "U", "L2", ";", "EOF"]
+        );
+        let starts_line = tokens
+            .iter()
+            .filter(|t| t.starts_line)
+            .map(|t| t.lexeme.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(starts_line, vec!["L1", "U"]);
+        assert!(lexer.diagnostics().is_empty());
+    }
+
+    #[test]
+    fn test_lex_unterminated_block_comment() {
+        let mut lexer = Lexer::new("L1 = {d1}; /* L2 = L1;".to_string());
+        let tokens = lexer.lex_all();
+        assert_eq!(tokens.len(), 7);
+        assert_eq!(lexer.diagnostics()[0].message, "unterminated block comment");
+        assert_eq!(lexer.diagnostics()[0].span.unwrap().column, 12);
+    }
+
+    #[test]
+    fn test_lex_range_and_complement() {
+        let mut lexer = Lexer::new("universe {d1..d12}; L1 = ~{d3} . L2;".to_string());
+        let tokens = lexer.lex_all();
+        let types = tokens
+            .iter()
+            .map(|t| t.token_type.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            &types[..9],
+            &[
+                TokenType::UniverseKw,
+                TokenType::SetOpen,
+                TokenType::Identifier,
+                TokenType::Range,
+                TokenType::Identifier,
+                TokenType::SetClose,
+                TokenType::NewLine,
+                TokenType::Identifier,
+                TokenType::Equals,
+            ]
+        );
+        assert_eq!(tokens[9].token_type, TokenType::Complement);
+        assert_eq!(lexer.diagnostics()[0].message, "unknown character `.`");
+    }
+
+    #[test]
+    fn test_lex_include() {
+        let mut lexer = Lexer::in_file("include \"shared.txt\";\ninclude \"open".to_string(), 2);
+        let tokens = lexer.lex_all();
+        assert_eq!(tokens[0].token_type, TokenType::Include);
+        assert_eq!(tokens[1].token_type, TokenType::String);
+        assert_eq!(tokens[1].string_value(), "shared.txt");
+        assert_eq!(tokens[1].span.file, 2);
+        assert_eq!(tokens[4].string_value(), "open");
+        assert_eq!(lexer.diagnostics()[0].message, "unterminated string");
+        assert_eq!(lexer.diagnostics()[0].span.unwrap().line, 2);
     }
 }
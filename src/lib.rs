@@ -0,0 +1,298 @@
+//! Data flow analysis over specifications of control flow graph data points.
+//!
+//! A specification is a list of equations such as `L2 = L1 U {d1};`. The
+//! usual entry points are [`parse_spec`] to turn text into a [`Spec`] and
+//! [`solve`] to run reaching definitions on it:
+//!
+//! ```
+//! let spec = lattis::parse_spec("L1 = {d1}; L2 = L1 U {d2};").unwrap();
+//! let solution = lattis::solve(&spec).unwrap();
+//! assert_eq!(solution.get("L2"), Some(vec!["d1", "d2"]));
+//! ```
+
+// Errors are reported as full diagnostics; they are only built on the slow
+// path, so their size does not matter.
+#![allow(clippy::result_large_err)]
+
+pub mod analysis;
+pub mod ast;
+pub mod cst;
+pub mod diagnostic;
+pub mod formatter;
+pub mod framework;
+pub mod graph;
+pub mod lexer;
+pub mod lint;
+mod names;
+mod node;
+mod parser;
+pub mod reduce;
+pub mod render;
+mod semantic;
+pub mod simplify;
+pub mod source;
+pub mod span;
+pub mod unparse;
+
+pub use analysis::{DataPoints, Solution};
+pub use diagnostic::{Diagnostic, Diagnostics, Severity};
+
+use source::SourceMap;
+use span::FileId;
+
+/// A parsed and simplified specification.
+#[derive(Debug, Clone)]
+pub struct Spec {
+    root: parser::Node,
+    ast: ast::Spec,
+}
+
+impl Spec {
+    /// Prints the syntax tree of the statements, as `lattis dump-ast` does.
+    pub fn print_syntax_tree(&self) {
+        self.root.print(0);
+    }
+
+    /// The statements as a typed syntax tree.
+    pub fn ast(&self) -> &ast::Spec {
+        &self.ast
+    }
+
+    /// Number of data flow equations in the specification.
+    pub fn len(&self) -> usize {
+        self.ast.equations.len()
+    }
+
+    /// Whether the specification has no data flow equations.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Lexes, parses and simplifies a specification.
+///
+/// Fails with every syntax error in the text if any equation is malformed.
+pub fn parse_spec(text: &str) -> Result<Spec, Diagnostics> {
+    let (spec, diagnostics) = parse_spec_partial(text);
+    if diagnostics.has_errors() {
+        return Err(diagnostics);
+    }
+    Ok(spec)
+}
+
+/// Like [`parse_spec`], but always returns the equations that did parse
+/// alongside the diagnostics for those that did not.
+///
+/// `include` directives in `text` are resolved relative to the current
+/// directory.
+pub fn parse_spec_partial(text: &str) -> (Spec, Diagnostics) {
+    let mut sources = SourceMap::new();
+    let file = sources.add("<input>", None, text);
+    parse_source(&mut sources, file)
+}
+
+/// Parses `file` of `sources` together with every file it includes, which
+/// are added to `sources`. Diagnostic spans carry the id of the file they
+/// point into; render them with [`Diagnostic::render_in`].
+pub fn parse_source(sources: &mut SourceMap, file: FileId) -> (Spec, Diagnostics) {
+    let (root, mut diagnostics) = source::parse_with_includes(sources, file);
+    let syntax_errors = diagnostics.iter().any(|d| d.severity == Severity::Error);
+    let root = node::simplify(&root);
+    let ast = ast::Spec::try_from(&root).expect("the parser only builds valid trees");
+    let spec = Spec { root, ast };
+
+    let (_, universe_diagnostics) = analysis::evaluate_universe(&spec.ast);
+    let (_, constant_diagnostics) = analysis::evaluate_constants(&spec.ast);
+    diagnostics.extend(universe_diagnostics);
+    diagnostics.extend(constant_diagnostics);
+    diagnostics.extend(semantic::check(&spec.ast));
+    if !syntax_errors {
+        diagnostics.extend(semantic::check_complete(&spec.ast));
+    }
+    diagnostics.sort_by_key(|d| d.span.map(|span| (span.file, span.start)));
+    (spec, diagnostics.into())
+}
+
+/// Runs reaching definitions to a fixpoint without printing anything.
+///
+/// Fails if the equations never settle.
+pub fn solve(spec: &Spec) -> Result<Solution, Diagnostic> {
+    analysis::reaching_definitions(&spec.ast)
+}
+
+/// Like [`solve`], but removes copy equations such as `L4 = L3;` first and
+/// solves the smaller system. The solution still has every data point;
+/// the counters are those of the smaller system.
+pub fn solve_reduced(spec: &Spec) -> Result<Solution, Diagnostic> {
+    let reduction = reduce::reduce(&spec.ast);
+    let solution = analysis::reaching_definitions(&reduction.spec)?;
+    Ok(reduction.expand_solution(&solution))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_spec_rejects_unknown_characters() {
+        let diagnostics = parse_spec("L1 = {d1} # U L1;").unwrap_err();
+        assert_eq!(diagnostics.len(), 1);
+        assert!(diagnostics.has_errors());
+    }
+
+    #[test]
+    fn test_parse_spec_partial_keeps_good_equations() {
+        let (spec, diagnostics) = parse_spec_partial("L1 = {d1}; L2 = L1 U; L3 = L1 / {d1};");
+        let errors = diagnostics.iter().filter(|d| d.severity == Severity::Error);
+        assert_eq!(errors.count(), 1);
+        assert_eq!(spec.len(), 2);
+        // `L2` is only missing because it did not parse.
+        assert!(diagnostics.iter().all(|d| !d.message.contains("numbering")));
+
+        let (_, diagnostics) = parse_spec_partial("L1 = {d1};\nL2 = L1 U;\nL3 = L2;");
+        assert_eq!(diagnostics.len(), 1);
+        assert!(diagnostics.has_errors());
+    }
+
+    #[test]
+    fn test_solve_intersection() {
+        let spec = parse_spec("L1 = {d1, d2}; L2 = {d2, d3}; L3 = L1 ∩ L2 U {d4} & L1;").unwrap();
+        let solution = solve(&spec).unwrap();
+        assert_eq!(solution.get("L3"), Some(vec!["d2"]));
+    }
+
+    #[test]
+    fn test_solve_reduced_matches_solve() {
+        let text = std::fs::read_to_string("oppg.txt").unwrap();
+        let spec = parse_spec(&text).unwrap();
+        let (full, reduced) = (solve(&spec).unwrap(), solve_reduced(&spec).unwrap());
+        assert_eq!(reduced.data_points, full.data_points);
+        assert_eq!(reduced.get("L30"), full.get("L4"));
+    }
+
+    #[test]
+    fn test_other_analyses() {
+        let spec = parse_spec("L1 = {e1, e2};\nL2 = L1 I L3 / {e2};\nL3 = L2;").unwrap();
+        let available = analysis::available_expressions(spec.ast()).unwrap();
+        assert_eq!(available.get("L3"), Some(vec!["e1"]));
+        assert_eq!(solve(&spec).unwrap().get("L3"), Some(vec![]));
+
+        let spec = parse_spec("L1 = L2 U {x};\nL2 = L3;\nL3 = {y};").unwrap();
+        let live = analysis::live_variables(spec.ast()).unwrap();
+        assert_eq!(live.get("L1"), Some(vec!["x", "y"]));
+        assert_eq!(live.iterations, 2);
+        assert_eq!(solve(&spec).unwrap().iterations, 4);
+    }
+
+    #[test]
+    fn test_worklist_matches_round_robin() {
+        use analysis::Analysis;
+        use framework::{Config, Solver, Strategy};
+
+        let text = std::fs::read_to_string("oppg.txt").unwrap();
+        let spec = parse_spec(&text).unwrap();
+        for analysis in [Analysis::ReachingDefinitions, Analysis::LiveVariables] {
+            let round_robin = analysis::solve(spec.ast(), analysis, Config::default()).unwrap();
+            for strategy in [Strategy::Fifo, Strategy::Lifo, Strategy::Priority] {
+                let config = Config {
+                    solver: Solver::Worklist(strategy),
+                    ..Config::default()
+                };
+                let worklist = analysis::solve(spec.ast(), analysis, config).unwrap();
+                assert_eq!(worklist.data_points, round_robin.data_points);
+                assert!(worklist.evaluations < round_robin.evaluations);
+            }
+        }
+    }
+
+    #[test]
+    fn test_jacobi_matches_gauss_seidel() {
+        use analysis::Analysis;
+        use framework::{Config, Mode};
+
+        let text = std::fs::read_to_string("oppg.txt").unwrap();
+        let spec = parse_spec(&text).unwrap();
+        let config = Config {
+            mode: Mode::Jacobi,
+            ..Config::default()
+        };
+        let jacobi = analysis::solve(spec.ast(), Analysis::ReachingDefinitions, config).unwrap();
+        let gauss_seidel = solve(&spec).unwrap();
+        assert_eq!(jacobi.data_points, gauss_seidel.data_points);
+        assert!(jacobi.iterations > gauss_seidel.iterations);
+    }
+
+    #[test]
+    fn test_solve_constants() {
+        let text = "let GEN10 = {d4, d5, d6}; let KILL14 = GEN10 U {d1};
+                    L9 = {d1, d2}; L10 = L9 / {d2} U GEN10; L14 = L10 / KILL14;";
+        let solution = solve(&parse_spec(text).unwrap()).unwrap();
+        assert_eq!(solution.get("L10"), Some(vec!["d1", "d4", "d5", "d6"]));
+        assert_eq!(solution.get("L14"), Some(vec![]));
+        assert_eq!(solution.get("GEN10"), None);
+    }
+
+    #[test]
+    fn test_constant_errors() {
+        let text = "let L1 = {d1}; let A = B; let B = A U C; let C = L1; let D = E;
+                    let D = {}; L1 = {};";
+        let diagnostics = parse_spec(text).unwrap_err();
+        let messages = diagnostics
+            .iter()
+            .map(|d| d.message.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            messages,
+            vec![
+                "`L1` is defined both as a constant and as a data point",
+                "constant `A` depends on itself",
+                "constant `C` refers to data point `L1`",
+                "cannot find `E` in this specification",
+                "constant `D` is defined twice",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_solve_ranges_and_complement() {
+        let text = "universe {d1..d6};
+                    let GEN = {d2..d4};
+                    L1 = ~GEN; L2 = ~{d1, d5..d6} / L1; L3 = ~(L1 U L2);";
+        let solution = solve(&parse_spec(text).unwrap()).unwrap();
+        assert_eq!(solution.get("L1"), Some(vec!["d1", "d5", "d6"]));
+        assert_eq!(solution.get("L2"), Some(vec!["d2", "d3", "d4"]));
+        assert_eq!(solution.get("L3"), Some(vec![]));
+    }
+
+    #[test]
+    fn test_universe_errors() {
+        let diagnostics = parse_spec("L1 = ~{d1};").unwrap_err();
+        assert_eq!(diagnostics.len(), 1);
+        assert!(diagnostics.iter().next().unwrap().help.is_some());
+
+        let text = "universe {d1} U L1; universe {d2}; L1 = {};";
+        let messages = parse_spec(text)
+            .unwrap_err()
+            .iter()
+            .filter(|d| d.severity == Severity::Error)
+            .map(|d| d.message.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            messages,
+            vec![
+                "the universe must be written with set literals",
+                "the universe is declared twice"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_solve_loop() {
+        let spec = parse_spec("L1 = {d1}; L2 = L1 U L3; L3 = L2 / {d1} U {d2};").unwrap();
+        let solution = solve(&spec).unwrap();
+        assert_eq!(solution.get("L2"), Some(vec!["d1", "d2"]));
+        assert_eq!(solution.get("L3"), Some(vec!["d2"]));
+        assert_eq!(solution.get("L4"), None);
+        assert_eq!(solution.iterations, 3);
+    }
+}
@@ -0,0 +1,320 @@
+//! Rules for suspicious but valid specifications, run by `lattis lint`.
+//!
+//! Every rule has an ID and a [`Level`]. Rules warn by default; a
+//! [`LintConfig`] can allow a rule, which silences it, or deny it, which
+//! turns its findings into errors.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::ast::{Expr, Ident, SetLit, Spec};
+use crate::diagnostic::{Diagnostic, Severity};
+use crate::semantic::{self, Role};
+use crate::simplify;
+
+/// What a rule does with its findings.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Level {
+    /// Nothing.
+    Allow,
+    /// Reports them as warnings.
+    Warn,
+    /// Reports them as errors.
+    Deny,
+}
+
+/// A lint rule.
+pub struct Rule {
+    /// The name `-A` and `-D` take.
+    pub id: &'static str,
+    /// What the rule looks for, for `--help`.
+    pub description: &'static str,
+}
+
+/// Every rule, in the order they are checked.
+pub const RULES: &[Rule] = &[
+    Rule {
+        id: "kill-regenerated",
+        description: "a kill is undone by generating the same definition right after, \
+                      as in `L21 / {d11} U {d11}`",
+    },
+    Rule {
+        id: "gen-killed",
+        description: "a set difference kills a definition its left side generates, \
+                      as in `{d7, d8} / {d7}`",
+    },
+    Rule {
+        id: "noop-kill",
+        description: "a definition is killed that no equation generates",
+    },
+    Rule {
+        id: "self-reference",
+        description: "an equation refers to its own data point, as in `L3 = L3 U L2`",
+    },
+    Rule {
+        id: "simplifiable",
+        description: "an expression can be written more simply, as in `L1 U {}`",
+    },
+];
+
+/// The rule named `id`.
+pub fn find_rule(id: &str) -> Option<&'static Rule> {
+    RULES.iter().find(|rule| rule.id == id)
+}
+
+/// The level of every rule.
+#[derive(Debug, Clone, Default)]
+pub struct LintConfig {
+    levels: HashMap<&'static str, Level>,
+}
+
+impl LintConfig {
+    /// Every rule at [`Level::Warn`].
+    pub fn new() -> LintConfig {
+        LintConfig::default()
+    }
+
+    /// Sets the level of the rule `id`, or of every rule for `all`.
+    pub fn set(&mut self, id: &str, level: Level) -> Result<(), String> {
+        if id == "all" {
+            for rule in RULES {
+                self.levels.insert(rule.id, level);
+            }
+            return Ok(());
+        }
+        let rule = find_rule(id).ok_or_else(|| format!("unknown lint rule `{}`", id))?;
+        self.levels.insert(rule.id, level);
+        Ok(())
+    }
+
+    /// The level of the rule `id`.
+    pub fn level(&self, id: &str) -> Level {
+        self.levels.get(id).copied().unwrap_or(Level::Warn)
+    }
+}
+
+/// Runs every rule not allowed by `config` on `spec`.
+pub fn lint(spec: &Spec, config: &LintConfig) -> Vec<Diagnostic> {
+    let generated = semantic::definitions_in(spec)
+        .into_iter()
+        .filter(|(_, role)| *role == Role::Generated)
+        .map(|(ident, _)| ident.name.clone())
+        .collect::<HashSet<_>>();
+    let mut linter = Linter {
+        config,
+        generated,
+        diagnostics: Vec::new(),
+    };
+
+    for binding in &spec.bindings {
+        linter.expression(&binding.value);
+    }
+    for equation in &spec.equations {
+        linter.expression(&equation.rhs);
+        let mut operands = Vec::new();
+        union_operands(&equation.rhs, &mut operands);
+        for reference in equation.rhs.vars() {
+            if reference.name != equation.lhs.name {
+                continue;
+            }
+            let diagnostic = Diagnostic::warning(format!("`{}` refers to itself", reference.name))
+                .with_label(reference.span, "reads the value being defined");
+            // Only a union keeps what the data point already holds.
+            let united = matches!(equation.rhs, Expr::Union { .. })
+                && operands.iter().any(
+                    |operand| matches!(operand, Expr::Var(ident) if ident.span == reference.span),
+                );
+            let diagnostic = if united {
+                diagnostic
+                    .with_help("a data point already includes its own value; drop the reference")
+            } else {
+                diagnostic
+            };
+            linter.report("self-reference", diagnostic);
+        }
+    }
+    linter.diagnostics
+}
+
+// The operands of the unions at the top of `expr`.
+fn union_operands<'a>(expr: &'a Expr, operands: &mut Vec<&'a Expr>) {
+    match expr {
+        Expr::Union { lhs, rhs, .. } => {
+            union_operands(lhs, operands);
+            union_operands(rhs, operands);
+        }
+        _ => operands.push(expr),
+    }
+}
+
+struct Linter<'a> {
+    config: &'a LintConfig,
+    generated: HashSet<String>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Linter<'_> {
+    fn report(&mut self, rule: &str, mut diagnostic: Diagnostic) {
+        match self.config.level(rule) {
+            Level::Allow => return,
+            Level::Warn => {}
+            Level::Deny => diagnostic.severity = Severity::Error,
+        }
+        self.diagnostics.push(diagnostic.with_code(rule));
+    }
+
+    fn expression(&mut self, expr: &Expr) {
+        expr.walk(&mut |expr| self.check(expr));
+
+        let simpler = simplify::expr(expr);
+        if !simplify::same(&simpler, expr) {
+            self.report(
+                "simplifiable",
+                Diagnostic::warning("expression can be simplified")
+                    .with_label(expr.span(), "this expression")
+                    .with_help(format!("write `{}` instead of `{}`", simpler, expr)),
+            );
+        }
+    }
+
+    fn check(&mut self, expr: &Expr) {
+        if let Expr::Union { lhs, rhs, .. } = expr {
+            if let (Expr::Diff { rhs: killed, .. }, Expr::SetLit(generated)) = (&**lhs, &**rhs) {
+                for ident in overlap(killed, generated) {
+                    self.report(
+                        "kill-regenerated",
+                        Diagnostic::warning(format!(
+                            "`{}` is killed and then generated again",
+                            ident.name
+                        ))
+                        .with_label(ident.span, "this kill has no effect"),
+                    );
+                }
+            }
+        }
+
+        if let Expr::Diff { lhs, rhs, .. } = expr {
+            if let Expr::SetLit(generated) = &**lhs {
+                for ident in overlap(rhs, generated) {
+                    self.report(
+                        "gen-killed",
+                        Diagnostic::warning(format!(
+                            "`{}` is generated and killed in the same expression",
+                            ident.name
+                        ))
+                        .with_label(ident.span, "killed here"),
+                    );
+                }
+            }
+            if let Expr::SetLit(killed) = &**rhs {
+                for ident in simplify::definitions(killed) {
+                    if !self.generated.contains(&ident.name) {
+                        self.report(
+                            "noop-kill",
+                            Diagnostic::warning(format!("killing `{}` has no effect", ident.name))
+                                .with_label(ident.span, "no equation generates this definition"),
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
+
+// The definitions of the set literal `killed` that `generated` also lists.
+fn overlap(killed: &Expr, generated: &SetLit) -> Vec<Ident> {
+    let Expr::SetLit(killed) = killed else {
+        return Vec::new();
+    };
+    let generated = simplify::definitions(generated)
+        .into_iter()
+        .map(|ident| ident.name)
+        .collect::<HashSet<_>>();
+    simplify::definitions(killed)
+        .into_iter()
+        .filter(|ident| generated.contains(&ident.name))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lint_text(text: &str, config: &LintConfig) -> Vec<Diagnostic> {
+        let spec = crate::parse_spec(text).unwrap();
+        lint(spec.ast(), config)
+    }
+
+    fn codes(diagnostics: &[Diagnostic]) -> Vec<&str> {
+        diagnostics
+            .iter()
+            .map(|d| d.code.as_deref().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_lint_example() {
+        let text = std::fs::read_to_string("oppg.txt").unwrap();
+        let diagnostics = lint_text(&text, &LintConfig::new());
+        assert_eq!(
+            codes(&diagnostics),
+            vec![
+                "gen-killed",
+                "simplifiable",
+                "kill-regenerated",
+                "simplifiable"
+            ]
+        );
+        assert_eq!(
+            diagnostics[0].message,
+            "`d7` is generated and killed in the same expression"
+        );
+        assert_eq!(
+            diagnostics[1].help.as_deref(),
+            Some(
+                "write `L13 / {d4, d5, d6} U {d8, d9, d10}` \
+                 instead of `L13 / {d4, d5, d6} U {d7, d8, d9, d10} / {d7}`"
+            )
+        );
+        assert_eq!(diagnostics[2].span.unwrap().line, 22);
+    }
+
+    #[test]
+    fn test_lint_rules() {
+        let text = "L1 = {d1} U {};\nL2 = L2 U L1 / {d9};\nL3 = L1 I L1;";
+        let diagnostics = lint_text(text, &LintConfig::new());
+        assert_eq!(
+            codes(&diagnostics),
+            vec![
+                "simplifiable",
+                "noop-kill",
+                "self-reference",
+                "simplifiable"
+            ]
+        );
+        assert!(diagnostics.iter().all(|d| d.severity == Severity::Warning));
+        assert!(diagnostics[2].help.is_some());
+
+        let diagnostics = lint_text("L1 = {d1} / L1;\nL2 = (L2 U L1) I L1;", &LintConfig::new());
+        assert_eq!(
+            codes(&diagnostics),
+            vec!["self-reference", "self-reference"]
+        );
+        assert!(diagnostics.iter().all(|d| d.help.is_none()));
+    }
+
+    #[test]
+    fn test_lint_config() {
+        let text = "L1 = {d1} U {};\nL2 = L2 U L1 / {d9};";
+        let mut config = LintConfig::new();
+        config.set("all", Level::Allow).unwrap();
+        config.set("self-reference", Level::Deny).unwrap();
+        let diagnostics = lint_text(text, &config);
+        assert_eq!(codes(&diagnostics), vec!["self-reference"]);
+        assert_eq!(diagnostics[0].severity, Severity::Error);
+        assert_eq!(
+            diagnostics[0].label.as_deref(),
+            Some("reads the value being defined")
+        );
+        assert!(config.set("no-such-rule", Level::Deny).is_err());
+    }
+}
@@ -1,21 +1,286 @@
-use lexer::Lexer;
-use parser::Parser;
-
-mod lexer;
-mod node;
-mod parser;
-mod analysis;
-
-fn generate_graph() -> parser::Node {
-    let text = std::fs::read_to_string("oppg.txt").unwrap();
-    let mut lexer = Lexer::new(text);
-    let tokens = lexer.lex_all();
-    let mut parser = Parser::new(tokens);
-    let mut root = parser.parse();
-    node::simplify(&mut root)
-}
-
-fn main() {
-    let root = generate_graph();
-    let iterations = analysis::reaching_definitions(&root);
+// Errors are reported as full diagnostics, as in the library.
+#![allow(clippy::result_large_err)]
+
+use std::io::Read;
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use cli::{Command, Format, Options};
+use lattis::framework::Config;
+use lattis::lexer::{Lexer, Token};
+use lattis::source::SourceMap;
+use lattis::{
+    analysis, formatter, lint, reduce, render, simplify, unparse, Diagnostic, Diagnostics,
+    Severity, Solution, Spec,
+};
+
+mod cli;
+
+struct Input {
+    name: String,
+    path: Option<PathBuf>,
+    text: String,
+}
+
+fn read_inputs(paths: &[String]) -> Result<Vec<Input>, String> {
+    if paths.is_empty() {
+        return read_inputs(&["-".to_string()]);
+    }
+
+    let mut inputs = Vec::new();
+    for path in paths {
+        let input = if path == "-" {
+            let mut text = String::new();
+            std::io::stdin()
+                .read_to_string(&mut text)
+                .map_err(|e| format!("<stdin>: {}", e))?;
+            Input {
+                name: "<stdin>".to_string(),
+                path: None,
+                text,
+            }
+        } else {
+            let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+            Input {
+                name: path.clone(),
+                path: Some(PathBuf::from(path)),
+                text,
+            }
+        };
+        inputs.push(input);
+    }
+    Ok(inputs)
+}
+
+fn lex(input: &Input) -> Vec<Token> {
+    let mut lexer = Lexer::new(input.text.clone());
+    lexer.lex_all()
+}
+
+// Parses `input` and the files it includes, printing any diagnostics. The
+// source map is needed to render later diagnostics about the spec.
+fn parse(input: &Input) -> Result<(Spec, SourceMap), u8> {
+    let mut sources = SourceMap::new();
+    let file = sources.add(&input.name, input.path.clone(), &input.text);
+    let (spec, diagnostics) = lattis::parse_source(&mut sources, file);
+    if !diagnostics.has_errors() {
+        // Warnings do not stop the command.
+        for diagnostic in &diagnostics {
+            eprintln!("{}", diagnostic.render_in(&sources));
+        }
+        return Ok((spec, sources));
+    }
+    Err(report(&diagnostics, &sources))
+}
+
+fn lint(options: &Options, input: &Input) -> u8 {
+    let (spec, sources) = match parse(input) {
+        Ok(parsed) => parsed,
+        Err(code) => return code,
+    };
+    let diagnostics = Diagnostics::from(lint::lint(spec.ast(), &options.lints));
+    if diagnostics.has_errors() {
+        return report(&diagnostics, &sources);
+    }
+    for diagnostic in &diagnostics {
+        eprintln!("{}", diagnostic.render_in(&sources));
+    }
+    cli::EXIT_SUCCESS
+}
+
+fn report(diagnostics: &Diagnostics, sources: &SourceMap) -> u8 {
+    for diagnostic in diagnostics {
+        eprintln!("{}", diagnostic.render_in(sources));
+    }
+    let errors = diagnostics
+        .iter()
+        .filter(|diagnostic| diagnostic.severity == Severity::Error)
+        .count();
+    match errors {
+        1 => eprintln!("error: aborting due to previous error"),
+        n => eprintln!("error: aborting due to {} previous errors", n),
+    }
+    cli::EXIT_FAILURE
+}
+
+// Prints `input` in canonical form, or with `--check` reports whether it
+// already is.
+fn fmt(options: &Options, input: &Input) -> u8 {
+    let formatted = match formatter::format(&input.text, options.notation) {
+        Ok(formatted) => formatted,
+        Err(diagnostics) => {
+            let mut sources = SourceMap::new();
+            sources.add(&input.name, input.path.clone(), &input.text);
+            return report(&diagnostics, &sources);
+        }
+    };
+
+    if !options.check {
+        print!("{}", formatted);
+        cli::EXIT_SUCCESS
+    } else if formatted == input.text {
+        cli::EXIT_SUCCESS
+    } else {
+        println!("would reformat {}", input.name);
+        cli::EXIT_FAILURE
+    }
+}
+
+fn analyze(options: &Options, spec: &Spec, config: Config) -> Result<Solution, Diagnostic> {
+    if options.reduce {
+        let reduction = reduce::reduce(spec.ast());
+        let solution = analysis::solve(&reduction.spec, options.analysis, config)?;
+        Ok(reduction.expand_solution(&solution))
+    } else {
+        analysis::solve(spec.ast(), options.analysis, config)
+    }
+}
+
+// Solves `input` once per evaluation order and prints how long each took.
+fn orders(options: &Options, input: &Input) -> u8 {
+    let (spec, sources) = match parse(input) {
+        Ok(parsed) => parsed,
+        Err(code) => return code,
+    };
+
+    let mut rows = Vec::new();
+    for (name, order) in cli::orders(options.seed) {
+        let config = Config {
+            order,
+            ..options.config
+        };
+        match analyze(options, &spec, config) {
+            Ok(solution) => rows.push((name, solution)),
+            Err(diagnostic) => return report(&Diagnostics::from(vec![diagnostic]), &sources),
+        }
+    }
+    match options.format {
+        Format::Text => {
+            println!("{:<10} {:>10} {:>11}", "order", "iterations", "evaluations");
+            for (name, solution) in &rows {
+                println!(
+                    "{:<10} {:>10} {:>11}",
+                    name, solution.iterations, solution.evaluations
+                );
+            }
+        }
+        Format::Json => {
+            let rows = rows
+                .iter()
+                .map(|(name, solution)| {
+                    format!(
+                        "{{\"order\":{},\"iterations\":{},\"evaluations\":{}}}",
+                        render::json_string(name),
+                        solution.iterations,
+                        solution.evaluations
+                    )
+                })
+                .collect::<Vec<_>>();
+            println!(
+                "{{\"file\":{},\"analysis\":{},\"orders\":[{}]}}",
+                render::json_string(&input.name),
+                render::json_string(options.analysis.name()),
+                rows.join(",")
+            );
+        }
+    }
+    cli::EXIT_SUCCESS
+}
+
+fn solve(options: &Options, input: &Input) -> u8 {
+    let (spec, sources) = match parse(input) {
+        Ok(parsed) => parsed,
+        Err(code) => return code,
+    };
+
+    let solution = match analyze(options, &spec, options.config) {
+        Ok(solution) => solution,
+        Err(diagnostic) => return report(&Diagnostics::from(vec![diagnostic]), &sources),
+    };
+    match options.format {
+        Format::Text => print!("{}", render::text(&solution)),
+        Format::Json => println!(
+            "{}",
+            render::json(&solution, &input.name, options.analysis.name())
+        ),
+    }
+    cli::EXIT_SUCCESS
+}
+
+fn run(options: &Options, input: &Input) -> u8 {
+    match options.command {
+        Command::Solve => solve(options, input),
+        Command::Orders => orders(options, input),
+        Command::Check => match parse(input) {
+            Ok((spec, _)) => {
+                if options.format == Format::Json {
+                    println!(
+                        "{{\"file\":{},\"equations\":{},\"errors\":0}}",
+                        render::json_string(&input.name),
+                        spec.len()
+                    );
+                } else {
+                    println!("{}: ok, {} equations", input.name, spec.len());
+                }
+                cli::EXIT_SUCCESS
+            }
+            Err(code) => code,
+        },
+        Command::Lint => lint(options, input),
+        Command::Fmt => fmt(options, input),
+        Command::Simplify => match parse(input) {
+            Ok((spec, _)) => {
+                let simplified = simplify::spec(spec.ast());
+                print!("{}", unparse::spec(&simplified, options.notation));
+                cli::EXIT_SUCCESS
+            }
+            Err(code) => code,
+        },
+        Command::DumpAst => match parse(input) {
+            Ok((spec, _)) => {
+                spec.print_syntax_tree();
+                cli::EXIT_SUCCESS
+            }
+            Err(code) => code,
+        },
+        Command::DumpTokens => {
+            for token in lex(input) {
+                println!("{:?} {:?}", token.token_type, token.lexeme);
+            }
+            cli::EXIT_SUCCESS
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    let options = match cli::parse_args(&args) {
+        Ok(options) => options,
+        Err(cli::Error::Help) => {
+            print!("{}", cli::USAGE);
+            return ExitCode::from(cli::EXIT_SUCCESS);
+        }
+        Err(e) => {
+            eprintln!("error: {}", e);
+            eprintln!("Run `lattis --help` for usage.");
+            return ExitCode::from(cli::EXIT_USAGE);
+        }
+    };
+
+    let inputs = match read_inputs(&options.paths) {
+        Ok(inputs) => inputs,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            return ExitCode::from(cli::EXIT_IO);
+        }
+    };
+
+    let mut status = cli::EXIT_SUCCESS;
+    for input in &inputs {
+        if inputs.len() > 1 && options.format == Format::Text {
+            println!("==> {} <==", input.name);
+        }
+        status = status.max(run(&options, input));
+    }
+    ExitCode::from(status)
 }
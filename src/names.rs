@@ -0,0 +1,129 @@
+use std::cmp::Ordering;
+
+// Splits a name into text and number chunks: `RD_entry(12)` becomes
+// `RD_entry(`, `12`, `)`.
+fn chunks(name: &str) -> Vec<&str> {
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    let mut digits = None;
+    for (i, c) in name.char_indices() {
+        let is_digit = c.is_ascii_digit();
+        if digits.is_some_and(|d| d != is_digit) {
+            chunks.push(&name[start..i]);
+            start = i;
+        }
+        digits = Some(is_digit);
+    }
+    if start < name.len() {
+        chunks.push(&name[start..]);
+    }
+    chunks
+}
+
+// Ranges larger than this are almost certainly typos.
+const MAX_RANGE: u64 = 100_000;
+
+/// Splits `d12` into `("d", "12")`. Returns `None` for names that do not end
+/// in digits.
+pub fn split_number(name: &str) -> Option<(&str, &str)> {
+    let prefix = name.trim_end_matches(|c: char| c.is_ascii_digit());
+    if prefix.len() == name.len() {
+        return None;
+    }
+    Some((prefix, &name[prefix.len()..]))
+}
+
+/// Expands the range `start..end`, e.g. `d1..d4` to `d1, d2, d3, d4`. Both
+/// ends need the same prefix. A zero-padded start such as `d01` pads every
+/// name to its width.
+pub fn expand_range(start: &str, end: &str) -> Result<Vec<String>, String> {
+    let (Some((start_prefix, start_digits)), Some((end_prefix, end_digits))) =
+        (split_number(start), split_number(end))
+    else {
+        return Err("range bounds must end in a number".to_string());
+    };
+    if start_prefix != end_prefix {
+        return Err(format!(
+            "range bounds have different prefixes `{}` and `{}`",
+            start_prefix, end_prefix
+        ));
+    }
+
+    let (Ok(first), Ok(last)) = (start_digits.parse::<u64>(), end_digits.parse::<u64>()) else {
+        return Err("range bounds are too large".to_string());
+    };
+    if first > last {
+        return Err(format!("range `{}..{}` is empty", start, end));
+    }
+    if last - first >= MAX_RANGE {
+        return Err(format!(
+            "range `{}..{}` has more than {} elements",
+            start, end, MAX_RANGE
+        ));
+    }
+
+    let width = if start_digits.starts_with('0') {
+        start_digits.len()
+    } else {
+        0
+    };
+    Ok((first..=last)
+        .map(|n| format!("{}{:0width$}", start_prefix, n, width = width))
+        .collect())
+}
+
+/// Compares names the way a person would: runs of digits compare by value,
+/// so `L2` sorts before `L10` and `IN_B2` before `IN_B10`.
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let a_chunks = chunks(a);
+    let b_chunks = chunks(b);
+    for (x, y) in a_chunks.iter().zip(b_chunks.iter()) {
+        let both_numbers = x.as_bytes()[0].is_ascii_digit() && y.as_bytes()[0].is_ascii_digit();
+        let ordering = if both_numbers {
+            let x_trimmed = x.trim_start_matches('0');
+            let y_trimmed = y.trim_start_matches('0');
+            x_trimmed
+                .len()
+                .cmp(&y_trimmed.len())
+                .then_with(|| x_trimmed.cmp(y_trimmed))
+        } else {
+            x.cmp(y)
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+    a_chunks.len().cmp(&b_chunks.len()).then_with(|| a.cmp(b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expand_range() {
+        assert_eq!(expand_range("d1", "d3").unwrap(), vec!["d1", "d2", "d3"]);
+        assert_eq!(expand_range("x@9", "x@10").unwrap(), vec!["x@9", "x@10"]);
+        assert_eq!(
+            expand_range("d08", "d10").unwrap(),
+            vec!["d08", "d09", "d10"]
+        );
+        assert_eq!(expand_range("d4", "d4").unwrap(), vec!["d4"]);
+        assert!(expand_range("d4", "d3").is_err());
+        assert!(expand_range("d1", "e3").is_err());
+        assert!(expand_range("d", "d3").is_err());
+        assert!(expand_range("d1", "d1000000").is_err());
+    }
+
+    #[test]
+    fn test_natural_cmp() {
+        let mut names = vec![
+            "L10", "d2", "L2", "L1", "IN_B10", "IN_B2", "Lfoo", "x@7", "L02",
+        ];
+        names.sort_by(|a, b| natural_cmp(a, b));
+        assert_eq!(
+            names,
+            vec!["IN_B2", "IN_B10", "L1", "L02", "L2", "L10", "Lfoo", "d2", "x@7"]
+        );
+    }
+}
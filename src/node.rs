@@ -1,10 +1,26 @@
+use crate::lexer::Token;
+use crate::names;
 use crate::parser::{self, NodeType};
 
+/// Drops the `Root` and single-operand `Body` wrappers the parser builds and
+/// expands definition ranges.
 pub fn simplify(node: &parser::Node) -> parser::Node {
     if node.node_type == NodeType::Root {
         return simplify(&node.children[0]);
     }
 
+    if node.node_type == NodeType::Set {
+        return parser::Node {
+            node_type: NodeType::Set,
+            children: node.children.iter().flat_map(expand_range).collect(),
+            token: node.token.clone(),
+        };
+    }
+
+    if node.node_type == NodeType::Body && node.children.len() == 1 {
+        return simplify(&node.children[0]);
+    }
+
     let mut new_node = parser::Node {
         node_type: node.node_type.clone(),
         children: Vec::new(),
@@ -15,11 +31,76 @@ pub fn simplify(node: &parser::Node) -> parser::Node {
         new_node.children.push(simplify(child).clone());
     }
 
-    if node.node_type == NodeType::Body {
-        if node.children.len() == 1 {
-            return simplify(&node.children[0]);
-        }
+    new_node
+}
+
+/// `d1..d3` becomes the definitions `d1`, `d2` and `d3`, all pointing at the
+/// range in the source.
+pub fn expand_range(node: &parser::Node) -> Vec<parser::Node> {
+    if node.node_type != NodeType::Range {
+        return vec![node.clone()];
+    }
+
+    let start = node.children[0].token.as_ref().unwrap();
+    let end = node.children[1].token.as_ref().unwrap();
+    let span = start.span.to(end.span);
+    match names::expand_range(&start.lexeme, &end.lexeme) {
+        Ok(definitions) => definitions
+            .into_iter()
+            .map(|lexeme| parser::Node {
+                node_type: NodeType::Definition,
+                children: Vec::new(),
+                token: Some(Token {
+                    lexeme,
+                    span,
+                    ..start.clone()
+                }),
+            })
+            .collect(),
+        Err(_) => vec![node.clone()],
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+    use crate::unparse::{self, Notation};
 
-    return new_node;
+    fn round_trip(text: &str, notation: Notation) -> String {
+        let mut lexer = Lexer::new(text.to_string());
+        let mut parser = Parser::new(lexer.lex_all());
+        let spec = ast::Spec::try_from(&simplify(&parser.parse())).unwrap();
+        unparse::spec(&spec, notation)
+    }
+
+    #[test]
+    fn test_round_trip_notation() {
+        let text = "L1 = {};\nL2 = L1 / {d1} U {d2, d3} I L1;\n";
+        assert_eq!(round_trip(text, Notation::Ascii), text);
+        let unicode = "L1 = ∅;\nL2 = L1 ∖ {d1} ∪ {d2, d3} ∩ L1;\n";
+        assert_eq!(round_trip(text, Notation::Unicode), unicode);
+        assert_eq!(round_trip(unicode, Notation::Ascii), text);
+    }
+
+    #[test]
+    fn test_simplify_expands_ranges() {
+        let text = "universe {d1..d3, x};\nL1 = ~(L2 U {d2}) / ~L3;\n";
+        let expected = "universe {d1, d2, d3, x};\nL1 = ~(L2 U {d2}) / ~L3;\n";
+        assert_eq!(round_trip(text, Notation::Ascii), expected);
+    }
+
+    #[test]
+    fn test_round_trip_parentheses() {
+        let text = "L1 = (L2 U L3) / L4 U L5 I (L6 / L7) U (L8 U L9);\n";
+        let expected = "L1 = (L2 U L3) / L4 U L5 I (L6 / L7) U (L8 U L9);\n";
+        assert_eq!(round_trip(text, Notation::Ascii), expected);
+        let text = "L1 = ((L2 U L3)) U (L4 / {d1});\n";
+        assert_eq!(
+            round_trip(text, Notation::Ascii),
+            "L1 = L2 U L3 U L4 / {d1};\n"
+        );
+    }
 }
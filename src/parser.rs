@@ -3,7 +3,10 @@ use std::{
     vec,
 };
 
+use crate::diagnostic::Diagnostic;
 use crate::lexer::{Token, TokenType};
+use crate::names;
+use crate::span::Span;
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum NodeType {
@@ -11,8 +14,15 @@ pub enum NodeType {
     DataPoint,
     Definition,
     Union,
+    Intersection,
     SetDifference,
     DataflowEquation,
+    LetBinding,
+    Constant,
+    Universe,
+    Include,
+    Range,
+    Complement,
     DataFlowEquationList,
     Body,
     Root,
@@ -39,7 +49,7 @@ impl Node {
             s = format!("{}\n{}", s, c);
         }
 
-        return s;
+        s
     }
 }
 
@@ -60,36 +70,82 @@ impl DerefMut for Node {
 pub struct Parser {
     tokens: Vec<Token>,
     cursor: usize,
-    stack: Vec<Node>,
+    diagnostics: Vec<Diagnostic>,
 }
 
 impl Parser {
-    pub fn new(tokens: Vec<Token>) -> Parser {
+    pub fn new(mut tokens: Vec<Token>) -> Parser {
+        if tokens.last().map(|t| &t.token_type) != Some(&TokenType::Eof) {
+            let end = tokens.last().map(|t| t.span).unwrap_or_default();
+            tokens.push(Token {
+                token_type: TokenType::Eof,
+                lexeme: "EOF".to_string(),
+                span: Span {
+                    start: end.end,
+                    column: end.column + end.len(),
+                    ..end
+                },
+                starts_line: false,
+            });
+        }
         Parser {
             tokens,
             cursor: 0,
-            stack: Vec::new(),
+            diagnostics: Vec::new(),
         }
     }
 
+    /// Errors reported by the last call to `parse`.
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
     fn expect(&self, token_type: TokenType) -> bool {
-        if self.cursor >= self.tokens.len() {
-            return false;
+        self.peek().token_type == token_type
+    }
+
+    fn peek(&self) -> &Token {
+        let last = self.tokens.len() - 1;
+        &self.tokens[self.cursor.min(last)]
+    }
+
+    fn unexpected(&self, expected: &str) -> Diagnostic {
+        let token = self.peek();
+        if token.token_type == TokenType::Subset {
+            return Self::unsupported(token, "data flow equations are written with `=`");
         }
-        self.tokens[self.cursor].token_type == token_type
+        Diagnostic::error(format!("expected {}, found {}", expected, token.describe()))
+            .with_label(token.span, format!("expected {}", expected))
+    }
+
+    // A known set symbol without an equivalent in the grammar.
+    fn unsupported(token: &Token, help: &str) -> Diagnostic {
+        Diagnostic::error(format!(
+            "subset constraints are not supported: {}",
+            token.describe()
+        ))
+        .with_label(token.span, "unsupported operator")
+        .with_help(help)
     }
 
-    fn expect_stack_top(&self, node_type: NodeType) -> bool {
-        self.stack[self.stack.len() - 1].node_type == node_type
+    fn consume(&mut self, token_type: TokenType) -> Result<Token, Diagnostic> {
+        if !self.expect(token_type.clone()) {
+            return Err(self.unexpected(token_type.describe()));
+        }
+        let token = self.peek().clone();
+        self.cursor += 1;
+        Ok(token)
     }
 
+    /// Parses every equation. Malformed equations are reported in
+    /// `diagnostics` and left out of the returned tree.
     pub fn parse(&mut self) -> Node {
-        let root = Node {
+        self.diagnostics.clear();
+        Node {
             node_type: NodeType::Root,
             children: vec![self.parse_data_flow_equation_list()],
             token: None,
-        };
-        root
+        }
     }
 
     fn parse_data_flow_equation_list(&mut self) -> Node {
@@ -98,9 +154,23 @@ impl Parser {
             children: Vec::new(),
             token: None,
         };
-        while self.cursor < self.tokens.len() {
-            let eq = self.parse_data_flow_equation();
-            node.children.push(eq);
+        while !self.expect(TokenType::Eof) {
+            let statement = if self.expect(TokenType::Let) {
+                self.parse_let_binding()
+            } else if self.expect(TokenType::UniverseKw) {
+                self.parse_universe()
+            } else if self.expect(TokenType::Include) {
+                self.parse_include()
+            } else {
+                self.parse_data_flow_equation()
+            };
+            match statement {
+                Ok(statement) => node.children.push(statement),
+                Err(diagnostic) => {
+                    self.diagnostics.push(diagnostic);
+                    self.synchronize();
+                }
+            }
 
             if self.expect(TokenType::NewLine) {
                 self.cursor += 1;
@@ -109,88 +179,245 @@ impl Parser {
         node
     }
 
-    fn parse_data_flow_equation(&mut self) -> Node {
-        let l = self.parse_data_point();
-        assert!(self.expect(TokenType::Equals));
+    // Skips to the `;` or line break ending the current equation.
+    fn synchronize(&mut self) {
+        if self.expect(TokenType::NewLine) || self.expect(TokenType::Eof) {
+            return;
+        }
         self.cursor += 1;
-        let body = self.parse_body();
-        let node = Node {
+        while !self.at_equation_end() {
+            self.cursor += 1;
+        }
+    }
+
+    // An equation ends at `;`, or at a line break when the next line does not
+    // continue the expression. Since the expression parser stops at the first
+    // token that cannot continue it, that is any token starting a line.
+    fn at_equation_end(&self) -> bool {
+        self.expect(TokenType::NewLine) || self.expect(TokenType::Eof) || self.peek().starts_line
+    }
+
+    fn parse_data_flow_equation(&mut self) -> Result<Node, Diagnostic> {
+        let l = self.parse_data_point()?;
+        // `L1 ⊆ ...` is reported, then parsed as the equation it was meant to
+        // be.
+        if self.expect(TokenType::Subset) {
+            let diagnostic =
+                Self::unsupported(self.peek(), "write the data flow equation with `=`");
+            self.diagnostics.push(diagnostic);
+            self.cursor += 1;
+        } else {
+            self.consume(TokenType::Equals)?;
+        }
+        let body = self.parse_body()?;
+        Ok(Node {
             node_type: NodeType::DataflowEquation,
             children: vec![l, body],
             token: None,
-        };
-        node
+        })
     }
 
-    fn parse_data_point(&mut self) -> Node {
-        assert!(self.expect(TokenType::DataPoint));
-        let node = Node {
-            node_type: NodeType::DataPoint,
+    // `let NAME = expression`, naming a set that is not a data flow unknown.
+    fn parse_let_binding(&mut self) -> Result<Node, Diagnostic> {
+        let keyword = self.consume(TokenType::Let)?;
+        if !self.expect(TokenType::Identifier) {
+            return Err(self.unexpected("constant name"));
+        }
+        let name = self.consume(TokenType::Identifier)?;
+        self.consume(TokenType::Equals)?;
+        let body = self.parse_body()?;
+        Ok(Node {
+            node_type: NodeType::LetBinding,
+            children: vec![
+                Node {
+                    node_type: NodeType::Constant,
+                    children: Vec::new(),
+                    token: Some(name),
+                },
+                body,
+            ],
+            token: Some(keyword),
+        })
+    }
+
+    // `universe expression`, the set that `~` complements against.
+    fn parse_universe(&mut self) -> Result<Node, Diagnostic> {
+        let keyword = self.consume(TokenType::UniverseKw)?;
+        let body = self.parse_body()?;
+        Ok(Node {
+            node_type: NodeType::Universe,
+            children: vec![body],
+            token: Some(keyword),
+        })
+    }
+
+    // `include "path"`. The node keeps the path token; the front end replaces
+    // it with the statements of the included file.
+    fn parse_include(&mut self) -> Result<Node, Diagnostic> {
+        self.consume(TokenType::Include)?;
+        if !self.expect(TokenType::String) {
+            return Err(self.unexpected("file name in quotes"));
+        }
+        let path = self.consume(TokenType::String)?;
+        if !self.at_equation_end() {
+            return Err(self.unexpected("`;`"));
+        }
+        Ok(Node {
+            node_type: NodeType::Include,
             children: Vec::new(),
-            token: Some(self.tokens[self.cursor].clone()),
-        };
-        self.cursor += 1;
-        node
+            token: Some(path),
+        })
     }
 
-    fn parse_body(&mut self) -> Node {
-        while self.cursor < self.tokens.len() && !self.expect(TokenType::NewLine) {
-            if self.expect(TokenType::Union) {
-                let union = self.parse_union();
-                self.stack.push(union);
-            } else if self.expect(TokenType::SetOpen) {
-                let set = self.parse_set();
-                self.stack.push(set);
-            } else if self.expect(TokenType::SetDifference) {
-                let set_difference = self.parse_set_difference();
-                self.stack.push(set_difference);
-            } else if self.expect(TokenType::DataPoint) {
-                let data_point = self.parse_data_point();
-                self.stack.push(data_point);
-            } else {
-                panic!("Unexpected token: {:?}", self.tokens[self.cursor]);
-            }
+    fn parse_data_point(&mut self) -> Result<Node, Diagnostic> {
+        if !self.expect(TokenType::Identifier) {
+            return Err(self.unexpected("data point"));
         }
+        let token = self.consume(TokenType::Identifier)?;
+        Ok(Node {
+            node_type: NodeType::DataPoint,
+            children: Vec::new(),
+            token: Some(token),
+        })
+    }
 
-        let node = Node {
+    fn parse_body(&mut self) -> Result<Node, Diagnostic> {
+        let expression = self.parse_expression(0)?;
+        if !self.at_equation_end() {
+            return Err(self.unexpected("operator or `;`"));
+        }
+        Ok(Node {
             node_type: NodeType::Body,
-            children: vec![self.stack.pop().unwrap()],
+            children: vec![expression],
             token: None,
-        };
-        node
+        })
     }
 
-    fn parse_set(&mut self) -> Node {
-        assert!(self.expect(TokenType::SetOpen));
-        self.cursor += 1;
-        let definition_list = self.parse_definition_list();
-        assert!(self.expect(TokenType::SetClose));
+    // Binding power of an infix operator. Every operator is left associative;
+    // intersection and difference bind tighter than union.
+    fn infix_binding_power(token_type: &TokenType) -> Option<(u8, NodeType)> {
+        match token_type {
+            TokenType::Union => Some((1, NodeType::Union)),
+            TokenType::Intersection => Some((2, NodeType::Intersection)),
+            TokenType::SetDifference => Some((2, NodeType::SetDifference)),
+            _ => None,
+        }
+    }
+
+    // Precedence climbing: parses operators binding tighter than `min_power`.
+    fn parse_expression(&mut self, min_power: u8) -> Result<Node, Diagnostic> {
+        let mut lhs = self.parse_operand()?;
+        while let Some((power, node_type)) = Self::infix_binding_power(&self.peek().token_type) {
+            if power <= min_power {
+                break;
+            }
+            let operator = self.peek().clone();
+            self.cursor += 1;
+            let rhs = self.parse_expression(power)?;
+            lhs = Node {
+                node_type,
+                children: vec![lhs, rhs],
+                token: Some(operator),
+            };
+        }
+        Ok(lhs)
+    }
+
+    fn parse_operand(&mut self) -> Result<Node, Diagnostic> {
+        match self.peek().token_type {
+            TokenType::Identifier => self.parse_data_point(),
+            TokenType::SetOpen | TokenType::EmptySet => self.parse_set(),
+            // Complement binds tighter than every infix operator.
+            TokenType::Complement => {
+                let operator = self.consume(TokenType::Complement)?;
+                let operand = self.parse_operand()?;
+                Ok(Node {
+                    node_type: NodeType::Complement,
+                    children: vec![operand],
+                    token: Some(operator),
+                })
+            }
+            TokenType::ParenOpen => {
+                self.cursor += 1;
+                let expression = self.parse_expression(0)?;
+                if !self.expect(TokenType::ParenClose) {
+                    return Err(self.unexpected("operator or `)`"));
+                }
+                self.cursor += 1;
+                Ok(expression)
+            }
+            _ => Err(self.unexpected("data point, set, `~` or `(`")),
+        }
+    }
+
+    fn parse_set(&mut self) -> Result<Node, Diagnostic> {
+        // The set keeps its opening `{` or `∅` for its span.
+        if self.expect(TokenType::EmptySet) {
+            let empty = self.consume(TokenType::EmptySet)?;
+            return Ok(Node {
+                node_type: NodeType::Set,
+                children: Vec::new(),
+                token: Some(empty),
+            });
+        }
+        let open = self.consume(TokenType::SetOpen)?;
+        let definition_list = self.parse_definition_list()?;
+        if !self.expect(TokenType::SetClose) {
+            let after_definition = self.tokens[self.cursor - 1].token_type == TokenType::Identifier;
+            return Err(if after_definition {
+                self.unexpected("`,` or `}`")
+            } else {
+                self.unexpected("definition or `}`")
+            });
+        }
         self.cursor += 1;
-        let node = Node {
+        Ok(Node {
             node_type: NodeType::Set,
             children: definition_list,
-            token: None,
-        };
-        node
+            token: Some(open),
+        })
     }
 
-    fn parse_definition_list(&mut self) -> Vec<Node> {
+    fn parse_definition_list(&mut self) -> Result<Vec<Node>, Diagnostic> {
         let mut definitions = Vec::new();
-        while self.cursor < self.tokens.len() {
-            let definition = self.parse_definition();
-            if definition.is_none() {
-                break;
+        while let Some(definition) = self.parse_definition() {
+            if self.expect(TokenType::Range) {
+                definitions.push(self.parse_range(definition)?);
+            } else {
+                definitions.push(definition);
             }
-            definitions.push(definition.unwrap());
-            if self.cursor < self.tokens.len() && self.expect(TokenType::Comma) {
-                self.cursor += 1;
+            if !self.expect(TokenType::Comma) {
+                break;
             }
+            self.cursor += 1;
+        }
+        Ok(definitions)
+    }
+
+    // `d1..d12`, called after the first definition.
+    fn parse_range(&mut self, start: Node) -> Result<Node, Diagnostic> {
+        let operator = self.consume(TokenType::Range)?;
+        let end = match self.parse_definition() {
+            Some(end) => end,
+            None => return Err(self.unexpected("end of range")),
+        };
+
+        let start_token = start.token.as_ref().unwrap();
+        let end_token = end.token.as_ref().unwrap();
+        if let Err(message) = names::expand_range(&start_token.lexeme, &end_token.lexeme) {
+            let span = start_token.span.to(end_token.span);
+            return Err(Diagnostic::error(message).with_label(span, "invalid range"));
         }
-        definitions
+        Ok(Node {
+            node_type: NodeType::Range,
+            children: vec![start, end],
+            token: Some(operator),
+        })
     }
 
+    // Names inside a set are definitions; everywhere else they are data points.
     fn parse_definition(&mut self) -> Option<Node> {
-        if self.expect(TokenType::Definition) {
+        if self.expect(TokenType::Identifier) {
             let node = Node {
                 node_type: NodeType::Definition,
                 children: Vec::new(),
@@ -202,39 +429,6 @@ impl Parser {
             None
         }
     }
-
-    fn parse_set_difference(&mut self) -> Node {
-        assert!(self.expect(TokenType::SetDifference));
-        assert!(self.expect_stack_top(NodeType::Set) || self.expect_stack_top(NodeType::DataPoint));
-        self.cursor += 1; // \
-        let lhs = self.stack.pop().unwrap(); // set or datapoint
-        let set = self.parse_set();
-        let node = Node {
-            node_type: NodeType::SetDifference,
-            children: vec![lhs, set],
-            token: None,
-        };
-        node
-    }
-
-    fn parse_union(&mut self) -> Node {
-        assert!(self.expect(TokenType::Union));
-        assert!(
-            self.expect_stack_top(NodeType::Set)
-                || self.expect_stack_top(NodeType::DataPoint)
-                || self.expect_stack_top(NodeType::Union)
-                || self.expect_stack_top(NodeType::SetDifference)
-        );
-        self.cursor += 1; // \
-        let lhs = self.stack.pop().unwrap();
-        let rhs = self.parse_body();
-        let node = Node {
-            node_type: NodeType::Union,
-            children: vec![lhs, rhs],
-            token: None,
-        };
-        node
-    }
 }
 
 #[cfg(test)]
@@ -251,7 +445,7 @@ mod tests {
         let mut lexer = Lexer::new(text);
         let tokens = lexer.lex_all();
         let mut parser = Parser::new(tokens);
-        let root = parser.parse_data_point();
+        let root = parser.parse_data_point().unwrap();
         assert_eq!(root.node_type, NodeType::DataPoint);
     }
 
@@ -261,7 +455,7 @@ mod tests {
         let mut lexer = Lexer::new(text);
         let tokens = lexer.lex_all();
         let mut parser = Parser::new(tokens);
-        let root = parser.parse_set();
+        let root = parser.parse_set().unwrap();
         assert_eq!(root.node_type, NodeType::Set);
         assert_eq!(root.children.len(), 0);
     }
@@ -282,7 +476,7 @@ mod tests {
         let mut lexer = Lexer::new(text);
         let tokens = lexer.lex_all();
         let mut parser = Parser::new(tokens);
-        let root = parser.parse_definition_list();
+        let root = parser.parse_definition_list().unwrap();
         assert_eq!(root.len(), 3);
     }
 
@@ -292,7 +486,7 @@ mod tests {
         let mut lexer = Lexer::new(text);
         let tokens = lexer.lex_all();
         let mut parser = Parser::new(tokens);
-        let root = parser.parse_set();
+        let root = parser.parse_set().unwrap();
         assert_eq!(root.node_type, NodeType::Set);
         assert_eq!(root.children.len(), 1);
     }
@@ -303,7 +497,7 @@ mod tests {
         let mut lexer = Lexer::new(text);
         let tokens = lexer.lex_all();
         let mut parser = Parser::new(tokens);
-        let root = parser.parse_set();
+        let root = parser.parse_set().unwrap();
         assert_eq!(root.node_type, NodeType::Set);
         assert_eq!(root.children.len(), 3);
     }
@@ -314,7 +508,7 @@ mod tests {
         let mut lexer = Lexer::new(text);
         let tokens = lexer.lex_all();
         let mut parser = Parser::new(tokens);
-        let root = parser.parse_body();
+        let root = parser.parse_body().unwrap();
         let set_d = &root.children[0];
         assert_eq!(set_d.node_type, NodeType::SetDifference);
         assert_eq!(set_d.children.len(), 2);
@@ -326,7 +520,7 @@ mod tests {
         let mut lexer = Lexer::new(text);
         let tokens = lexer.lex_all();
         let mut parser = Parser::new(tokens);
-        let root = parser.parse_body();
+        let root = parser.parse_body().unwrap();
         let set_d = &root.children[0];
         assert_eq!(set_d.node_type, NodeType::SetDifference);
         assert_eq!(set_d.children.len(), 2);
@@ -338,7 +532,7 @@ mod tests {
         let mut lexer = Lexer::new(text);
         let tokens = lexer.lex_all();
         let mut parser = Parser::new(tokens);
-        let root = parser.parse_body();
+        let root = parser.parse_body().unwrap();
         let set_d = &root.children[0];
         assert_eq!(set_d.node_type, NodeType::Union);
         assert_eq!(set_d.children.len(), 2);
@@ -351,7 +545,7 @@ mod tests {
         let mut lexer = Lexer::new(text);
         let tokens = lexer.lex_all();
         let mut parser = Parser::new(tokens);
-        let root = parser.parse_data_flow_equation();
+        let root = parser.parse_data_flow_equation().unwrap();
         assert_eq!(root.node_type, NodeType::DataflowEquation);
         assert_eq!(root.children.len(), 2);
         // write to file
@@ -385,7 +579,7 @@ mod tests {
         let s = format!("{:#?}", tokens);
         file.write_all(s.as_bytes()).unwrap();
         let mut parser = Parser::new(tokens.clone());
-        let root = parser.parse_data_flow_equation();
+        let root = parser.parse_data_flow_equation().unwrap();
         assert_eq!(root.node_type, NodeType::DataflowEquation);
         assert_eq!(root.children.len(), 2);
         // write to file
@@ -393,4 +587,246 @@ mod tests {
         let s = root.to_string(0);
         file.write_all(s.as_bytes()).unwrap();
     }
+
+    fn parse_error(text: &str) -> Diagnostic {
+        let mut lexer = Lexer::new(text.to_string());
+        let mut parser = Parser::new(lexer.lex_all());
+        parser.parse();
+        assert_eq!(parser.diagnostics().len(), 1);
+        parser.diagnostics()[0].clone()
+    }
+
+    #[test]
+    fn test_parse_error_missing_equals() {
+        let diagnostic = parse_error("L1 = {};\nL2 L1 U {d1};");
+        assert_eq!(diagnostic.message, "expected `=`, found `L1`");
+        let span = diagnostic.span.unwrap();
+        assert_eq!((span.line, span.column), (2, 4));
+    }
+
+    #[test]
+    fn test_parse_error_missing_operand() {
+        let diagnostic = parse_error("L1 = U {d1};");
+        assert_eq!(
+            diagnostic.message,
+            "expected data point, set, `~` or `(`, found `U`"
+        );
+        let diagnostic = parse_error("L1 = L2 U;");
+        assert_eq!(
+            diagnostic.message,
+            "expected data point, set, `~` or `(`, found `;`"
+        );
+    }
+
+    #[test]
+    fn test_parse_error_missing_operator() {
+        let diagnostic = parse_error("L1 = L2 {d1};");
+        assert_eq!(diagnostic.message, "expected operator or `;`, found `{`");
+    }
+
+    #[test]
+    fn test_parse_error_subset() {
+        let diagnostic = parse_error("L1 = {d1} ⊆ L2;");
+        assert_eq!(
+            diagnostic.message,
+            "subset constraints are not supported: `⊆`"
+        );
+        let diagnostic = parse_error("L1 ⊇ {d1};\nL2 = L1;");
+        assert_eq!(
+            diagnostic.help.as_deref(),
+            Some("write the data flow equation with `=`")
+        );
+    }
+
+    #[test]
+    fn test_parse_error_unclosed_set() {
+        let diagnostic = parse_error("L1 = L2 / {d1, U};");
+        assert_eq!(diagnostic.message, "expected definition or `}`, found `U`");
+        let diagnostic = parse_error("L1 = L2 / {d1 d2};");
+        assert_eq!(diagnostic.message, "expected `,` or `}`, found `d2`");
+    }
+
+    #[test]
+    fn test_parse_recovers_at_semicolon() {
+        let text = "L1 = {};\nL2 L1 U {d1};\nL3 = L2 U;\nL4 = L3 / {d1};\nL5 = {d2 L4};";
+        let mut lexer = Lexer::new(text.to_string());
+        let mut parser = Parser::new(lexer.lex_all());
+        let root = parser.parse();
+        let lines = parser
+            .diagnostics()
+            .iter()
+            .map(|d| d.span.unwrap().line)
+            .collect::<Vec<_>>();
+        assert_eq!(lines, vec![2, 3, 5]);
+
+        let equations = &root.children[0];
+        assert_eq!(equations.len(), 2);
+        assert_eq!(equations[1][0].token.as_ref().unwrap().lexeme, "L4");
+    }
+
+    #[test]
+    fn test_parse_unicode_empty_set() {
+        let text = "L1 ∖ ∅".to_string();
+        let mut lexer = Lexer::new(text);
+        let tokens = lexer.lex_all();
+        let mut parser = Parser::new(tokens);
+        let root = parser.parse_body().unwrap();
+        let set_d = &root.children[0];
+        assert_eq!(set_d.node_type, NodeType::SetDifference);
+        assert_eq!(set_d.children[1].node_type, NodeType::Set);
+        assert_eq!(set_d.children[1].len(), 0);
+    }
+
+    #[test]
+    fn test_parse_identifiers() {
+        let text = "IN_B3 = OUT_B2 U {x@7, RD_entry(4)}".to_string();
+        let mut lexer = Lexer::new(text);
+        let tokens = lexer.lex_all();
+        let mut parser = Parser::new(tokens);
+        let root = parser.parse_data_flow_equation().unwrap();
+        assert_eq!(root[0].node_type, NodeType::DataPoint);
+        let union = &root[1][0];
+        assert_eq!(union[0].node_type, NodeType::DataPoint);
+        let set = &union[1];
+        assert_eq!(set.len(), 2);
+        assert_eq!(set[0].node_type, NodeType::Definition);
+        assert_eq!(set[1].token.as_ref().unwrap().lexeme, "RD_entry(4)");
+    }
+
+    fn parse_expression(text: &str) -> Node {
+        let mut lexer = Lexer::new(text.to_string());
+        let mut parser = Parser::new(lexer.lex_all());
+        let root = parser.parse_body().unwrap();
+        crate::node::simplify(&root)
+    }
+
+    // Writes the tree fully parenthesised.
+    fn grouping(node: &Node) -> String {
+        match node.node_type {
+            NodeType::Union | NodeType::Intersection | NodeType::SetDifference => format!(
+                "({} {} {})",
+                grouping(&node[0]),
+                node.token.as_ref().unwrap().lexeme,
+                grouping(&node[1])
+            ),
+            NodeType::Set => format!("{{{}}}", node.len()),
+            _ => node.token.as_ref().unwrap().lexeme.clone(),
+        }
+    }
+
+    #[test]
+    fn test_parse_intersection_precedence() {
+        let root = parse_expression("L1 U L2 ∩ L3 / {d1} U L4");
+        assert_eq!(grouping(&root), "((L1 U ((L2 ∩ L3) / {1})) U L4)");
+    }
+
+    #[test]
+    fn test_parse_left_associative() {
+        let root = parse_expression("L1 U L2 U L3");
+        assert_eq!(grouping(&root), "((L1 U L2) U L3)");
+        let root = parse_expression("L1 / {d1} / L2 I L3");
+        assert_eq!(grouping(&root), "(((L1 / {1}) / L2) I L3)");
+    }
+
+    #[test]
+    fn test_parse_parentheses() {
+        let root = parse_expression("(L1 U L2) / (L3 U {d1, d2})");
+        assert_eq!(grouping(&root), "((L1 U L2) / (L3 U {2}))");
+        let root = parse_expression("L1 U (L2 U ((L3)))");
+        assert_eq!(grouping(&root), "(L1 U (L2 U L3))");
+    }
+
+    #[test]
+    fn test_parse_error_unclosed_parenthesis() {
+        let diagnostic = parse_error("L1 = (L2 U L3;");
+        assert_eq!(diagnostic.message, "expected operator or `)`, found `;`");
+        let diagnostic = parse_error("L1 = L2);");
+        assert_eq!(diagnostic.message, "expected operator or `;`, found `)`");
+    }
+
+    #[test]
+    fn test_parse_newline_terminated_equations() {
+        let text = "L1 = {d1}\nL2 = L1\n  U {d2} // gen\nL3 = L2 /\n  {d1}; L4 = L3\n";
+        let mut lexer = Lexer::new(text.to_string());
+        let mut parser = Parser::new(lexer.lex_all());
+        let root = crate::node::simplify(&parser.parse());
+        assert!(parser.diagnostics().is_empty());
+        assert_eq!(root.len(), 4);
+        assert_eq!(root[1][1].node_type, NodeType::Union);
+        assert_eq!(root[2][1].node_type, NodeType::SetDifference);
+    }
+
+    #[test]
+    fn test_parse_recovers_at_line_break() {
+        let text = "L1 = {d1} {d2}\nL2 = L1 U\nL3 = = L2\nL4 = L1";
+        let mut lexer = Lexer::new(text.to_string());
+        let mut parser = Parser::new(lexer.lex_all());
+        let root = crate::node::simplify(&parser.parse());
+        let lines = parser
+            .diagnostics()
+            .iter()
+            .map(|d| d.span.unwrap().line)
+            .collect::<Vec<_>>();
+        assert_eq!(lines, vec![1, 3]);
+        assert_eq!(root.len(), 1);
+        assert_eq!(root[0][0].token.as_ref().unwrap().lexeme, "L4");
+    }
+
+    #[test]
+    fn test_parse_let_binding() {
+        let text = "let GEN10 = {d4, d5, d6};\nlet KILL14 = GEN10;\nL10 = L9 / KILL14 U GEN10;";
+        let mut lexer = Lexer::new(text.to_string());
+        let mut parser = Parser::new(lexer.lex_all());
+        let root = crate::node::simplify(&parser.parse());
+        assert!(parser.diagnostics().is_empty());
+        assert_eq!(root[0].node_type, NodeType::LetBinding);
+        assert_eq!(root[0][0].node_type, NodeType::Constant);
+        assert_eq!(root[1][1].node_type, NodeType::DataPoint);
+        assert_eq!(root[2].node_type, NodeType::DataflowEquation);
+    }
+
+    #[test]
+    fn test_parse_range_and_complement() {
+        let text = "universe {d1..d12};\nL1 = ~{d3, d5..d7} U ~L2 / L3;";
+        let mut lexer = Lexer::new(text.to_string());
+        let mut parser = Parser::new(lexer.lex_all());
+        let root = crate::node::simplify(&parser.parse());
+        assert!(parser.diagnostics().is_empty());
+        assert_eq!(root[0].node_type, NodeType::Universe);
+        let union = &root[1][1];
+        assert_eq!(union[0].node_type, NodeType::Complement);
+        assert_eq!(union[1].node_type, NodeType::SetDifference);
+        assert_eq!(union[1][0].node_type, NodeType::Complement);
+    }
+
+    #[test]
+    fn test_parse_error_invalid_range() {
+        let diagnostic = parse_error("L1 = {d1..e3};");
+        assert_eq!(
+            diagnostic.message,
+            "range bounds have different prefixes `d` and `e`"
+        );
+        assert_eq!(diagnostic.span.unwrap().len(), 6);
+        let diagnostic = parse_error("L1 = {d1..};");
+        assert_eq!(diagnostic.message, "expected end of range, found `}`");
+    }
+
+    #[test]
+    fn test_parse_include() {
+        let mut lexer = Lexer::new("include \"procs/p.txt\";\nL1 = {};".to_string());
+        let mut parser = Parser::new(lexer.lex_all());
+        let root = crate::node::simplify(&parser.parse());
+        assert!(parser.diagnostics().is_empty());
+        assert_eq!(root[0].node_type, NodeType::Include);
+        assert_eq!(
+            root[0].token.as_ref().unwrap().string_value(),
+            "procs/p.txt"
+        );
+
+        let diagnostic = parse_error("include procs;");
+        assert_eq!(
+            diagnostic.message,
+            "expected file name in quotes, found `procs`"
+        );
+    }
 }
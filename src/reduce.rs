@@ -0,0 +1,190 @@
+//! Removes copy equations such as `L4 = L3;` before solving.
+//!
+//! A copy equation gives its data point the same value as another one, so it
+//! can be dropped once every reference to it refers to the copied data point
+//! instead. The smaller system has the same fixpoint on the data points it
+//! keeps, and [`Reduction::expand`] fills in the removed ones.
+//!
+//! Copies that only lead to other copies in a cycle, such as `L1 = L2;
+//! L2 = L1;`, are kept as they are.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::analysis::{DataPoints, Solution};
+use crate::ast::{Expr, Spec};
+
+/// A specification without its copy equations, and how to put them back.
+#[derive(Debug, Clone)]
+pub struct Reduction {
+    /// The specification without its copy equations.
+    pub spec: Spec,
+    // Every removed data point with the kept data point it has the value of,
+    // in the order of the original equations.
+    copies: Vec<(String, String)>,
+}
+
+impl Reduction {
+    /// Number of equations removed.
+    pub fn removed(&self) -> usize {
+        self.copies.len()
+    }
+
+    /// The kept data point whose value `data_point` was removed in favour of.
+    pub fn source(&self, data_point: &str) -> Option<&str> {
+        self.copies
+            .iter()
+            .find(|(copy, _)| copy == data_point)
+            .map(|(_, source)| source.as_str())
+    }
+
+    /// A state of the reduced system with the removed data points added back.
+    pub fn expand(&self, data_points: &DataPoints) -> DataPoints {
+        let mut expanded = data_points.clone();
+        for (copy, source) in &self.copies {
+            let value = data_points.get(source).cloned().unwrap_or_default();
+            expanded.insert(copy.clone(), value);
+        }
+        expanded
+    }
+
+    /// A solution of the reduced system with the removed data points added
+    /// back to its final state, its history and its convergence rounds.
+    pub fn expand_solution(&self, solution: &Solution) -> Solution {
+        let mut converged = solution.converged.clone();
+        for (copy, source) in &self.copies {
+            if let Some(&round) = solution.converged.get(source) {
+                converged.insert(copy.clone(), round);
+            }
+        }
+        Solution {
+            data_points: self.expand(&solution.data_points),
+            history: solution
+                .history
+                .iter()
+                .map(|state| self.expand(state))
+                .collect(),
+            converged,
+            ..solution.clone()
+        }
+    }
+}
+
+/// Removes the copy equations of `spec`.
+pub fn reduce(spec: &Spec) -> Reduction {
+    let mut defined = HashMap::new();
+    for equation in &spec.equations {
+        *defined.entry(equation.lhs.name.as_str()).or_insert(0) += 1;
+    }
+    // A data point defined twice is an error, and not a copy of anything.
+    let copied = spec
+        .equations
+        .iter()
+        .filter_map(|equation| match &equation.rhs {
+            Expr::Var(source) if defined.get(source.name.as_str()) == Some(&1) => {
+                Some((equation.lhs.name.as_str(), source.name.as_str()))
+            }
+            _ => None,
+        })
+        .filter(|(copy, _)| defined[copy] == 1)
+        .collect::<HashMap<_, _>>();
+
+    let mut copies = Vec::new();
+    for equation in &spec.equations {
+        let copy = equation.lhs.name.as_str();
+        if copied.contains_key(copy) {
+            if let Some(source) = resolve(&copied, copy) {
+                copies.push((copy.to_string(), source.to_string()));
+            }
+        }
+    }
+
+    let sources = copies
+        .iter()
+        .map(|(copy, source)| (copy.as_str(), source.as_str()))
+        .collect::<HashMap<_, _>>();
+    let mut reduced = spec.clone();
+    reduced
+        .equations
+        .retain(|equation| !sources.contains_key(equation.lhs.name.as_str()));
+    for equation in &mut reduced.equations {
+        substitute(&mut equation.rhs, &sources);
+    }
+
+    Reduction {
+        spec: reduced,
+        copies,
+    }
+}
+
+// Follows `name` through copies to a data point that is not a copy, or
+// `None` if the copies go round in a cycle.
+fn resolve<'a>(copied: &HashMap<&'a str, &'a str>, name: &'a str) -> Option<&'a str> {
+    let mut seen = HashSet::new();
+    let mut current = name;
+    while let Some(&next) = copied.get(current) {
+        if !seen.insert(current) {
+            return None;
+        }
+        current = next;
+    }
+    Some(current)
+}
+
+fn substitute(expr: &mut Expr, sources: &HashMap<&str, &str>) {
+    match expr {
+        Expr::Var(ident) => {
+            if let Some(source) = sources.get(ident.name.as_str()) {
+                ident.name = source.to_string();
+            }
+        }
+        Expr::SetLit(_) => {}
+        Expr::Complement { operand, .. } => substitute(operand, sources),
+        Expr::Union { lhs, rhs, .. }
+        | Expr::Intersection { lhs, rhs, .. }
+        | Expr::Diff { lhs, rhs, .. } => {
+            substitute(lhs, sources);
+            substitute(rhs, sources);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis;
+
+    fn reduce_text(text: &str) -> (Spec, Reduction) {
+        let spec = crate::parse_spec(text).unwrap().ast().clone();
+        let reduction = reduce(&spec);
+        (spec, reduction)
+    }
+
+    #[test]
+    fn test_reduce_chains() {
+        let (_, reduction) = reduce_text("L1 = {d1};\nL2 = L1;\nL3 = L2;\nL4 = L3 U L2 / {d1};");
+        assert_eq!(reduction.removed(), 2);
+        assert_eq!(reduction.source("L3"), Some("L1"));
+        assert_eq!(reduction.source("L4"), None);
+        assert_eq!(
+            reduction.spec.to_string(),
+            "L1 = {d1};\nL4 = L1 U L1 / {d1};\n"
+        );
+    }
+
+    #[test]
+    fn test_reduce_keeps_copy_cycles() {
+        let (_, reduction) = reduce_text("L1 = L2;\nL2 = L1;\nL3 = L1;\nL4 = L3 U {d1};");
+        assert_eq!(reduction.removed(), 0);
+        assert_eq!(reduction.spec.equations.len(), 4);
+    }
+
+    #[test]
+    fn test_reduce_example_keeps_its_solution() {
+        let text = std::fs::read_to_string("oppg.txt").unwrap();
+        let (spec, reduction) = reduce_text(&text);
+        assert_eq!(reduction.removed(), 18);
+        let full = analysis::reaching_definitions(&spec).unwrap();
+        let reduced = analysis::reaching_definitions(&reduction.spec).unwrap();
+        assert_eq!(reduction.expand(&reduced.data_points), full.data_points);
+    }
+}
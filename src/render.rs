@@ -0,0 +1,118 @@
+//! Renders a [`Solution`] as text for people or as JSON for other programs.
+
+use crate::analysis::{sorted_datapoints, DataPoints, Solution};
+use crate::names::natural_cmp;
+
+/// One line per data point, as in `L2: {d1, d2, }`.
+pub fn data_points(data_points: &DataPoints) -> String {
+    let mut out = String::new();
+    for (key, points) in sorted_datapoints(data_points) {
+        out.push_str(&format!("{}: {{", key));
+        for point in points {
+            out.push_str(&format!("{}, ", point));
+        }
+        out.push_str("}\n");
+    }
+    out
+}
+
+/// Every round of the solution, headed `Iteration N`.
+pub fn text(solution: &Solution) -> String {
+    let mut out = String::new();
+    for (round, state) in solution.history.iter().enumerate() {
+        out.push_str(&format!("Iteration {}\n", round + 1));
+        out.push_str(&data_points(state));
+    }
+    out
+}
+
+/// The solution of `file` as a single JSON object.
+pub fn json(solution: &Solution, file: &str, analysis: &str) -> String {
+    let states = solution
+        .history
+        .iter()
+        .enumerate()
+        .map(|(round, state)| {
+            format!(
+                "{{\"iteration\":{},\"data_points\":{}}}",
+                round + 1,
+                json_data_points(state)
+            )
+        })
+        .collect::<Vec<_>>();
+    let mut converged = solution.converged.iter().collect::<Vec<_>>();
+    converged.sort_by(|a, b| natural_cmp(a.0, b.0));
+    let converged = converged
+        .into_iter()
+        .map(|(name, round)| format!("{}:{}", json_string(name), round))
+        .collect::<Vec<_>>();
+    format!(
+        "{{\"file\":{},\"analysis\":{},\"iterations\":[{}],\"converged_after\":{},\
+         \"converged\":{{{}}},\"evaluations\":{},\"updates\":{}}}",
+        json_string(file),
+        json_string(analysis),
+        states.join(","),
+        solution.iterations,
+        converged.join(","),
+        solution.evaluations,
+        solution.updates
+    )
+}
+
+/// `s` as a JSON string literal.
+pub fn json_string(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn json_data_points(data_points: &DataPoints) -> String {
+    let entries = sorted_datapoints(data_points)
+        .into_iter()
+        .map(|(key, points)| {
+            let points = points.iter().map(|p| json_string(p)).collect::<Vec<_>>();
+            format!("{}:[{}]", json_string(key), points.join(","))
+        })
+        .collect::<Vec<_>>();
+    format!("{{{}}}", entries.join(","))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn solve(text: &str) -> Solution {
+        crate::solve(&crate::parse_spec(text).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_render_text() {
+        let solution = solve("L1 = {d2, d1};\nL2 = L1 / {d1};");
+        assert_eq!(
+            text(&solution),
+            "Iteration 1\nL1: {d1, d2, }\nL2: {d2, }\nIteration 2\nL1: {d1, d2, }\nL2: {d2, }\n"
+        );
+    }
+
+    #[test]
+    fn test_render_json() {
+        let solution = solve("L1 = {d1};\nL2 = L1;");
+        assert_eq!(
+            json(&solution, "a\"b", "rd"),
+            "{\"file\":\"a\\\"b\",\"analysis\":\"rd\",\"iterations\":[\
+             {\"iteration\":1,\"data_points\":{\"L1\":[\"d1\"],\"L2\":[\"d1\"]}},\
+             {\"iteration\":2,\"data_points\":{\"L1\":[\"d1\"],\"L2\":[\"d1\"]}}],\
+             \"converged_after\":2,\"converged\":{\"L1\":1,\"L2\":1},\
+             \"evaluations\":4,\"updates\":2}"
+        );
+    }
+}
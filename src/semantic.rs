@@ -0,0 +1,481 @@
+//! Checks that run on a parsed specification before it is solved.
+//!
+//! Undefined references and duplicate equations are errors: the first would
+//! leave a data point without a value and the second would silently replace
+//! an equation. Unused data points, definitions that are killed but never
+//! generated, data points killed or complemented by an equation they depend
+//! on, and gaps in numbered names are warnings.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::ast::{Element, Expr, Ident, Spec};
+use crate::diagnostic::Diagnostic;
+use crate::graph::DependencyGraph;
+use crate::names::{self, natural_cmp};
+
+pub fn check(spec: &Spec) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    check_duplicates(spec, &mut diagnostics);
+    check_generated(spec, &mut diagnostics);
+    check_cycles(spec, &mut diagnostics);
+    diagnostics
+}
+
+/// Reports undefined references, unused data points and gaps in numbered
+/// names. An equation dropped by a syntax error shows up in each of them, so
+/// these only run on specifications without syntax errors.
+pub fn check_complete(spec: &Spec) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    check_references(spec, &mut diagnostics);
+    check_unused(spec, &mut diagnostics);
+    let data_points = spec.equations.iter().map(|equation| &equation.lhs);
+    report_gaps("data point", data_points, &mut diagnostics);
+    let mut definitions = Vec::new();
+    for (ident, _) in definitions_in(spec) {
+        definitions.push(ident);
+    }
+    report_gaps("definition", definitions.into_iter(), &mut diagnostics);
+    diagnostics
+}
+
+fn check_duplicates(spec: &Spec, diagnostics: &mut Vec<Diagnostic>) {
+    let mut defined = HashSet::new();
+    for equation in &spec.equations {
+        if !defined.insert(equation.lhs.name.as_str()) {
+            diagnostics.push(
+                Diagnostic::error(format!(
+                    "data point `{}` is defined twice",
+                    equation.lhs.name
+                ))
+                .with_label(equation.lhs.span, "redefined here")
+                .with_help("merge the equations with `U`"),
+            );
+        }
+    }
+}
+
+fn check_references(spec: &Spec, diagnostics: &mut Vec<Diagnostic>) {
+    let constants = spec
+        .bindings
+        .iter()
+        .map(|binding| binding.name.name.as_str())
+        .collect::<HashSet<_>>();
+    let defined = spec
+        .equations
+        .iter()
+        .map(|equation| equation.lhs.name.as_str())
+        .collect::<HashSet<_>>();
+    let definitions = definitions_in(spec)
+        .into_iter()
+        .map(|(ident, _)| ident.name.as_str())
+        .collect::<HashSet<_>>();
+    for equation in &spec.equations {
+        for reference in equation.rhs.vars() {
+            let name = reference.name.as_str();
+            if defined.contains(name) || constants.contains(name) {
+                continue;
+            }
+            let near_miss = |names: &HashSet<&str>| {
+                let mut matches = names
+                    .iter()
+                    .filter(|other| other.eq_ignore_ascii_case(name))
+                    .collect::<Vec<_>>();
+                matches.sort_by(|a, b| natural_cmp(a, b));
+                matches.first().map(|other| other.to_string())
+            };
+            let help = near_miss(&defined)
+                .or_else(|| near_miss(&constants))
+                .map(|other| format!("did you mean `{}`?", other))
+                .or_else(|| {
+                    near_miss(&definitions)
+                        .map(|other| format!("did you mean the definition `{{{}}}`?", other))
+                })
+                .or_else(|| miscased_help(name));
+            let mut diagnostic =
+                Diagnostic::error(format!("cannot find `{}` in this specification", name))
+                    .with_label(reference.span, "not a data point or constant");
+            if let Some(help) = help {
+                diagnostic = diagnostic.with_help(help);
+            }
+            diagnostics.push(diagnostic);
+        }
+    }
+}
+
+// `l3` and `D3` break the convention of `L` for data points and `d` for
+// definitions.
+fn miscased_help(name: &str) -> Option<String> {
+    match names::split_number(name)? {
+        ("l", number) => Some(format!(
+            "data points start with an uppercase `L`: did you mean `L{}`?",
+            number
+        )),
+        ("D", number) => Some(format!(
+            "definitions start with a lowercase `d` and are written in a set: did you mean `{{d{}}}`?",
+            number
+        )),
+        _ => None,
+    }
+}
+
+// The last equation is the exit of the program, so nothing refers to it.
+fn check_unused(spec: &Spec, diagnostics: &mut Vec<Diagnostic>) {
+    let used = spec
+        .equations
+        .iter()
+        .flat_map(|equation| equation.rhs.vars())
+        .map(|reference| reference.name.as_str())
+        .collect::<HashSet<_>>();
+    let Some((_, equations)) = spec.equations.split_last() else {
+        return;
+    };
+    for equation in equations {
+        if !used.contains(equation.lhs.name.as_str()) {
+            diagnostics.push(
+                Diagnostic::warning(format!("data point `{}` is never used", equation.lhs.name))
+                    .with_label(equation.lhs.span, "defined here"),
+            );
+        }
+    }
+}
+
+// Whether a definition is added to or removed from a data point.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub(crate) enum Role {
+    Generated,
+    Killed,
+    Universe,
+}
+
+// Every definition written in the specification with the role it plays.
+// Definitions in a constant take the role of the place the constant is used.
+pub(crate) fn definitions_in(spec: &Spec) -> Vec<(&Ident, Role)> {
+    let bindings = spec
+        .bindings
+        .iter()
+        .map(|binding| (binding.name.name.as_str(), &binding.value))
+        .collect::<HashMap<_, _>>();
+    let mut collector = Collector {
+        bindings,
+        expanding: Vec::new(),
+        found: Vec::new(),
+    };
+    for universe in &spec.universes {
+        collector.collect(&universe.value, Role::Universe);
+    }
+    for equation in &spec.equations {
+        collector.collect(&equation.rhs, Role::Generated);
+    }
+    collector.found
+}
+
+struct Collector<'a> {
+    bindings: HashMap<&'a str, &'a Expr>,
+    // Constants being collected, to stop at cyclic bindings.
+    expanding: Vec<&'a str>,
+    found: Vec<(&'a Ident, Role)>,
+}
+
+impl<'a> Collector<'a> {
+    fn collect(&mut self, expr: &'a Expr, role: Role) {
+        match expr {
+            Expr::Var(ident) => {
+                let name = ident.name.as_str();
+                if let Some(value) = self.bindings.get(name).copied() {
+                    if !self.expanding.contains(&name) {
+                        self.expanding.push(name);
+                        self.collect(value, role);
+                        self.expanding.pop();
+                    }
+                }
+            }
+            Expr::SetLit(set) => {
+                for element in &set.elements {
+                    match element {
+                        Element::Definition(ident) => self.found.push((ident, role)),
+                        Element::Range { start, end } => {
+                            self.found.push((start, role));
+                            self.found.push((end, role));
+                        }
+                    }
+                }
+            }
+            Expr::Diff { lhs, rhs, .. } => {
+                self.collect(lhs, role);
+                let killed = if role == Role::Generated {
+                    Role::Killed
+                } else {
+                    role
+                };
+                self.collect(rhs, killed);
+            }
+            _ => {
+                for operand in expr.operands() {
+                    self.collect(operand, role);
+                }
+            }
+        }
+    }
+}
+
+fn check_generated(spec: &Spec, diagnostics: &mut Vec<Diagnostic>) {
+    let definitions = definitions_in(spec);
+    let generated = definitions
+        .iter()
+        .filter(|(_, role)| *role == Role::Generated)
+        .map(|(ident, _)| ident.name.as_str())
+        .collect::<HashSet<_>>();
+    let mut reported = HashSet::new();
+    for (ident, role) in definitions {
+        let name = ident.name.as_str();
+        if role == Role::Generated || generated.contains(name) || !reported.insert(name) {
+            continue;
+        }
+        let label = match role {
+            Role::Killed => "killed here",
+            _ => "declared here",
+        };
+        diagnostics.push(
+            Diagnostic::warning(format!("definition `{}` is never generated", name))
+                .with_label(ident.span, label),
+        );
+    }
+}
+
+// A data point killed or complemented by an equation in its own cycle makes
+// that equation shrink as the data point grows, so the cycle may never
+// settle.
+fn check_cycles(spec: &Spec, diagnostics: &mut Vec<Diagnostic>) {
+    let graph = DependencyGraph::from_spec(spec);
+    let mut component = vec![0; graph.len()];
+    for (c, members) in graph.components().into_iter().enumerate() {
+        for i in members {
+            component[i] = c;
+        }
+    }
+    let mut index = HashMap::new();
+    for i in 0..graph.len() {
+        index.entry(graph.name(i)).or_insert(i);
+    }
+
+    for (i, equation) in spec.equations.iter().enumerate() {
+        let mut shrinking = Vec::new();
+        shrinking_in(&equation.rhs, None, &mut shrinking);
+        for (ident, how) in shrinking {
+            let Some(&j) = index.get(ident.name.as_str()) else {
+                continue;
+            };
+            if component[j] != component[i] {
+                continue;
+            }
+            diagnostics.push(
+                Diagnostic::warning(format!(
+                    "`{}` is {} by an equation it depends on",
+                    ident.name, how
+                ))
+                .with_label(ident.span, format!("{} here", how))
+                .with_help(
+                    "the equations may never settle; kill or complement a set literal \
+                     or a constant",
+                ),
+            );
+        }
+    }
+}
+
+// The names in `expr` that make it smaller as they grow: those under an odd
+// number of `/` right-hand sides and `~`, with how the innermost of them
+// treats the name.
+fn shrinking_in<'a>(
+    expr: &'a Expr,
+    negated: Option<&'static str>,
+    found: &mut Vec<(&'a Ident, &'static str)>,
+) {
+    let flip = |how| match negated {
+        Some(_) => None,
+        None => Some(how),
+    };
+    match expr {
+        Expr::Var(ident) => {
+            if let Some(how) = negated {
+                found.push((ident, how));
+            }
+        }
+        Expr::Diff { lhs, rhs, .. } => {
+            shrinking_in(lhs, negated, found);
+            shrinking_in(rhs, flip("killed"), found);
+        }
+        Expr::Complement { operand, .. } => shrinking_in(operand, flip("complemented"), found),
+        _ => {
+            for operand in expr.operands() {
+                shrinking_in(operand, negated, found);
+            }
+        }
+    }
+}
+
+// Reports numbers missing between names with the same prefix, such as `L6`
+// when there are `L5` and `L7`.
+fn report_gaps<'a>(
+    kind: &str,
+    idents: impl Iterator<Item = &'a Ident>,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let mut groups: HashMap<&str, HashMap<u64, &Ident>> = HashMap::new();
+    for ident in idents {
+        let Some((prefix, digits)) = names::split_number(&ident.name) else {
+            continue;
+        };
+        if let Ok(number) = digits.parse::<u64>() {
+            groups
+                .entry(prefix)
+                .or_default()
+                .entry(number)
+                .or_insert(ident);
+        }
+    }
+
+    let mut prefixes = groups.keys().copied().collect::<Vec<_>>();
+    prefixes.sort();
+    for prefix in prefixes {
+        let mut numbers = groups[prefix].iter().collect::<Vec<_>>();
+        numbers.sort_by_key(|(number, _)| **number);
+        for pair in numbers.windows(2) {
+            let ((&low, _), (&high, after)) = (pair[0], pair[1]);
+            if high == low + 1 {
+                continue;
+            }
+            let missing = if high == low + 2 {
+                format!("`{}{}`", prefix, low + 1)
+            } else {
+                format!("`{}{}`..`{}{}`", prefix, low + 1, prefix, high - 1)
+            };
+            diagnostics.push(
+                Diagnostic::warning(format!("{} numbering skips {}", kind, missing))
+                    .with_label(after.span, format!("after `{}{}`", prefix, low)),
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Severity;
+
+    fn check_text(text: &str) -> Vec<Diagnostic> {
+        let (spec, _) = crate::parse_spec_partial(text);
+        let mut diagnostics = check(spec.ast());
+        diagnostics.extend(check_complete(spec.ast()));
+        diagnostics.sort_by_key(|d| d.span.map(|span| span.start));
+        diagnostics
+    }
+
+    fn messages(diagnostics: &[Diagnostic]) -> Vec<&str> {
+        diagnostics.iter().map(|d| d.message.as_str()).collect()
+    }
+
+    #[test]
+    fn test_check_clean_example() {
+        let text = std::fs::read_to_string("oppg.txt").unwrap();
+        assert!(check_text(&text).is_empty());
+    }
+
+    #[test]
+    fn test_check_undefined_and_duplicate() {
+        let diagnostics = check_text("L1 = {d1};\nL2 = L1 U L31;\nL1 = L2;");
+        assert_eq!(
+            messages(&diagnostics),
+            vec![
+                "cannot find `L31` in this specification",
+                "data point `L1` is defined twice",
+            ]
+        );
+        assert!(diagnostics.iter().all(|d| d.severity == Severity::Error));
+        assert_eq!(diagnostics[0].span.unwrap().column, 11);
+        assert_eq!(diagnostics[1].span.unwrap().line, 3);
+    }
+
+    #[test]
+    fn test_check_miscased_names() {
+        let diagnostics = check_text("L1 = D1 U l2;");
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(
+            diagnostics[0].help.as_deref(),
+            Some(
+                "definitions start with a lowercase `d` and are written in a set: did you mean `{d1}`?"
+            )
+        );
+        assert_eq!(
+            diagnostics[1].help.as_deref(),
+            Some("data points start with an uppercase `L`: did you mean `L2`?")
+        );
+
+        let diagnostics = check_text("Entry = {d1};\nL2 = entry U D1;");
+        assert_eq!(diagnostics[0].message, "data point `Entry` is never used");
+        assert_eq!(
+            diagnostics[1].help.as_deref(),
+            Some("did you mean `Entry`?")
+        );
+        assert_eq!(
+            diagnostics[2].help.as_deref(),
+            Some("did you mean the definition `{d1}`?")
+        );
+    }
+
+    #[test]
+    fn test_check_warnings() {
+        let text = "let K = {d9};\nL1 = {d1} / {d2};\nL2 = {d1} / K;\nL3 = L1;\nL5 = L3;";
+        let diagnostics = check_text(text);
+        assert_eq!(
+            messages(&diagnostics),
+            vec![
+                "definition `d9` is never generated",
+                "definition numbering skips `d3`..`d8`",
+                "definition `d2` is never generated",
+                "data point `L2` is never used",
+                "data point numbering skips `L4`",
+            ]
+        );
+        assert!(diagnostics.iter().all(|d| d.severity == Severity::Warning));
+        assert_eq!(diagnostics[4].span.unwrap().line, 5);
+    }
+
+    #[test]
+    fn test_check_killed_in_cycle() {
+        let text = "L1 = {d1} / L1;\nL2 = L1 U L4 / {d1};\nL3 = L2 / L1;\nL4 = {d2} / (L3 / L2);";
+        let diagnostics = check_text(text);
+        assert_eq!(
+            messages(&diagnostics),
+            vec![
+                "`L1` is killed by an equation it depends on",
+                "`L3` is killed by an equation it depends on",
+            ]
+        );
+        assert_eq!(diagnostics[0].span.unwrap().column, 13);
+        assert_eq!(diagnostics[1].span.unwrap().line, 4);
+        assert_eq!(diagnostics[0].severity, Severity::Warning);
+
+        let diagnostics = check_text("L1 = {d1};\nL2 = L1 / L3;\nL3 = L2;");
+        assert_eq!(
+            messages(&diagnostics),
+            vec!["`L3` is killed by an equation it depends on"]
+        );
+    }
+
+    #[test]
+    fn test_check_complemented_in_cycle() {
+        let text =
+            "universe {d1, d2};\nL1 = ~L1 U {d1, d2};\nL2 = ~(L1 U L3);\nL3 = L2 / ~L2;\nL4 = ~L3;";
+        let diagnostics = check_text(text);
+        assert_eq!(
+            messages(&diagnostics),
+            vec![
+                "`L1` is complemented by an equation it depends on",
+                "`L3` is complemented by an equation it depends on",
+            ]
+        );
+        assert_eq!(diagnostics[0].label.as_deref(), Some("complemented here"));
+        assert_eq!(diagnostics[1].span.unwrap().line, 3);
+    }
+}
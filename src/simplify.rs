@@ -0,0 +1,355 @@
+//! Algebraic simplification of equations.
+//!
+//! This works on the typed syntax tree rather than on the parser's tree,
+//! whose simplification runs before the semantic
+//! checks and the linter and must keep the equations as written. Every
+//! rewrite gives an equal set for every value of the data points:
+//!
+//! - unions are flattened, and their set literals merged into one, with `{}`
+//!   and repeated operands dropped;
+//! - differences and intersections of two set literals are folded;
+//! - `(A U G) / K` with a set literal `K` becomes `A / K U (G / K)`, so a
+//!   literal `G` disjoint from `K` moves out of the difference unchanged;
+//! - `A / K U G` with set literals `K` and `G` kills only the definitions of
+//!   `K` that `G` does not generate again;
+//! - `A / K1 / K2` with set literals `K1` and `K2` becomes `A / (K1 U K2)`;
+//! - `A U {}`, `A / {}`, `{} / A`, `A I {}`, `A / A`, `A I A` and `A U A` are
+//!   reduced.
+
+use std::collections::HashSet;
+
+use crate::ast::{Element, Expr, Ident, SetLit, Spec};
+use crate::names::{self, natural_cmp};
+use crate::span::Span;
+
+/// `spec` with every expression simplified.
+pub fn spec(spec: &Spec) -> Spec {
+    let mut simplified = spec.clone();
+    for universe in &mut simplified.universes {
+        universe.value = expr(&universe.value);
+    }
+    for binding in &mut simplified.bindings {
+        binding.value = expr(&binding.value);
+    }
+    for equation in &mut simplified.equations {
+        equation.rhs = expr(&equation.rhs);
+    }
+    simplified
+}
+
+/// An expression equal to `expr`, and no larger.
+pub fn expr(expr: &Expr) -> Expr {
+    match expr {
+        Expr::Var(_) | Expr::SetLit(_) => expr.clone(),
+        Expr::Complement { operand, op } => Expr::Complement {
+            operand: Box::new(self::expr(operand)),
+            op: *op,
+        },
+        Expr::Union { lhs, rhs, op } => union(vec![self::expr(lhs), self::expr(rhs)], *op),
+        Expr::Intersection { lhs, rhs, op } => intersection(self::expr(lhs), self::expr(rhs), *op),
+        Expr::Diff { lhs, rhs, op } => difference(self::expr(lhs), self::expr(rhs), *op),
+    }
+}
+
+// The definitions of a set literal, ranges expanded.
+pub(crate) fn definitions(set: &SetLit) -> Vec<Ident> {
+    let mut definitions = Vec::new();
+    for element in &set.elements {
+        match element {
+            Element::Definition(ident) => definitions.push(ident.clone()),
+            Element::Range { start, end } => {
+                let span = start.span.to(end.span);
+                let expanded = names::expand_range(&start.name, &end.name).unwrap_or_default();
+                definitions.extend(expanded.into_iter().map(|name| Ident { name, span }));
+            }
+        }
+    }
+    definitions
+}
+
+// A set literal of `definitions` in natural order, without duplicates.
+fn literal(mut definitions: Vec<Ident>, span: Span) -> Expr {
+    definitions.sort_by(|a, b| natural_cmp(&a.name, &b.name));
+    definitions.dedup_by(|a, b| a.name == b.name);
+    Expr::SetLit(SetLit {
+        elements: definitions.into_iter().map(Element::Definition).collect(),
+        span,
+    })
+}
+
+fn is_empty_set(expr: &Expr) -> bool {
+    matches!(expr, Expr::SetLit(set) if set.elements.is_empty())
+}
+
+// Expressions are compared by how they are written, ignoring spans.
+pub(crate) fn same(a: &Expr, b: &Expr) -> bool {
+    a.to_string() == b.to_string()
+}
+
+fn union(operands: Vec<Expr>, op: Span) -> Expr {
+    let mut flat = Vec::new();
+    for operand in operands {
+        flatten_union(operand, &mut flat);
+    }
+
+    // All set literals merge into the first one.
+    let mut merged = None;
+    let mut rest: Vec<Option<Expr>> = Vec::new();
+    for operand in flat {
+        match operand {
+            Expr::SetLit(set) => {
+                let (span, mut definitions) = merged.take().unwrap_or_else(|| {
+                    rest.push(None);
+                    (set.span, Vec::new())
+                });
+                definitions.extend(self::definitions(&set));
+                merged = Some((span, definitions));
+            }
+            operand if rest.iter().flatten().any(|other| same(other, &operand)) => {}
+            operand => rest.push(Some(operand)),
+        }
+    }
+
+    // `A / K U G` need not kill what `G` generates again.
+    let generated = merged
+        .as_ref()
+        .map(|(_, definitions)| definitions.iter().map(|ident| ident.name.clone()).collect())
+        .unwrap_or_default();
+    let mut operands = Vec::new();
+    for operand in rest {
+        match operand {
+            Some(operand) => operands.push(unkill(operand, &generated)),
+            None => {
+                let (span, definitions) = merged.take().unwrap();
+                if !definitions.is_empty() || operands.is_empty() {
+                    operands.push(literal(definitions, span));
+                }
+            }
+        }
+    }
+    // `{} U A` keeps only `A`.
+    if operands.len() > 1 && is_empty_set(&operands[0]) {
+        operands.remove(0);
+    }
+
+    let mut operands = operands.into_iter();
+    let first = operands.next().unwrap();
+    operands.fold(first, |lhs, rhs| Expr::Union {
+        lhs: Box::new(lhs),
+        rhs: Box::new(rhs),
+        op,
+    })
+}
+
+fn unkill(operand: Expr, generated: &HashSet<String>) -> Expr {
+    match operand {
+        Expr::Diff { lhs, rhs, op } if matches!(*rhs, Expr::SetLit(_)) => {
+            let Expr::SetLit(killed) = *rhs else {
+                unreachable!()
+            };
+            let kept = definitions(&killed)
+                .into_iter()
+                .filter(|ident| !generated.contains(&ident.name))
+                .collect();
+            difference(*lhs, literal(kept, killed.span), op)
+        }
+        operand => operand,
+    }
+}
+
+fn flatten_union(expr: Expr, flat: &mut Vec<Expr>) {
+    match expr {
+        Expr::Union { lhs, rhs, .. } => {
+            flatten_union(*lhs, flat);
+            flatten_union(*rhs, flat);
+        }
+        expr => flat.push(expr),
+    }
+}
+
+fn intersection(lhs: Expr, rhs: Expr, op: Span) -> Expr {
+    match (&lhs, &rhs) {
+        _ if same(&lhs, &rhs) => lhs,
+        _ if is_empty_set(&lhs) => lhs,
+        _ if is_empty_set(&rhs) => rhs,
+        (Expr::SetLit(left), Expr::SetLit(right)) => {
+            let right = names_of(right);
+            let kept = definitions(left)
+                .into_iter()
+                .filter(|ident| right.contains(&ident.name))
+                .collect();
+            literal(kept, left.span)
+        }
+        _ => Expr::Intersection {
+            lhs: Box::new(lhs),
+            rhs: Box::new(rhs),
+            op,
+        },
+    }
+}
+
+fn names_of(set: &SetLit) -> HashSet<String> {
+    definitions(set)
+        .into_iter()
+        .map(|ident| ident.name)
+        .collect()
+}
+
+fn difference(lhs: Expr, rhs: Expr, op: Span) -> Expr {
+    if is_empty_set(&rhs) {
+        return lhs;
+    }
+    if is_empty_set(&lhs) || same(&lhs, &rhs) {
+        return literal(Vec::new(), op);
+    }
+    let Expr::SetLit(killed) = &rhs else {
+        return Expr::Diff {
+            lhs: Box::new(lhs),
+            rhs: Box::new(rhs),
+            op,
+        };
+    };
+
+    match lhs {
+        Expr::SetLit(generated) => {
+            let killed = names_of(killed);
+            let kept = definitions(&generated)
+                .into_iter()
+                .filter(|ident| !killed.contains(&ident.name))
+                .collect();
+            literal(kept, generated.span)
+        }
+        // `A / K1 / K2` kills `K1 U K2` at once.
+        Expr::Diff {
+            lhs: inner,
+            rhs: first,
+            op: inner_op,
+        } if matches!(*first, Expr::SetLit(_)) => {
+            let Expr::SetLit(first) = *first else {
+                unreachable!()
+            };
+            let mut both = definitions(&first);
+            both.extend(definitions(killed));
+            difference(*inner, literal(both, first.span), inner_op)
+        }
+        // `(A U G) / K` is `A / K U G / K`; only worth it with a literal `G`.
+        Expr::Union { op: union_op, .. }
+            if union_operands(&lhs)
+                .iter()
+                .any(|operand| matches!(operand, Expr::SetLit(_))) =>
+        {
+            let (literals, others): (Vec<_>, Vec<_>) = union_operands(&lhs)
+                .into_iter()
+                .partition(|operand| matches!(operand, Expr::SetLit(_)));
+            let mut operands = Vec::new();
+            if !others.is_empty() {
+                operands.push(difference(union(others, union_op), rhs.clone(), op));
+            }
+            for generated in literals {
+                operands.push(difference(generated, rhs.clone(), op));
+            }
+            union(operands, union_op)
+        }
+        lhs => Expr::Diff {
+            lhs: Box::new(lhs),
+            rhs: Box::new(rhs),
+            op,
+        },
+    }
+}
+
+fn union_operands(expr: &Expr) -> Vec<Expr> {
+    let mut flat = Vec::new();
+    flatten_union(expr.clone(), &mut flat);
+    flat
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Simplifies the equation `L0 = <text>` and checks the result still
+    // solves to the same sets.
+    fn simplify(text: &str) -> String {
+        let source = format!("L1 = {{d1}};\nL2 = {{d2, d3}};\nL3 = L1;\nL0 = {};", text);
+        let spec = crate::parse_spec(&source).unwrap();
+        let simplified = self::spec(spec.ast());
+
+        let original = crate::analysis::reaching_definitions(spec.ast())
+            .unwrap()
+            .data_points;
+        let rewritten = crate::analysis::reaching_definitions(&simplified)
+            .unwrap()
+            .data_points;
+        assert_eq!(original, rewritten, "`{}` changed meaning", text);
+        simplified.equations[3].rhs.to_string()
+    }
+
+    #[test]
+    fn test_flatten_and_merge_unions() {
+        assert_eq!(simplify("{d2} U L1 U ({d1} U L2)"), "{d1, d2} U L1 U L2");
+        assert_eq!(simplify("L1 U {d4} U {d3, d4}"), "L1 U {d3, d4}");
+    }
+
+    #[test]
+    fn test_drop_empty_set() {
+        assert_eq!(simplify("L1 U {} U L2"), "L1 U L2");
+        assert_eq!(simplify("{} U {}"), "{}");
+        assert_eq!(simplify("L1 / {}"), "L1");
+        assert_eq!(simplify("{} / L1"), "{}");
+        assert_eq!(simplify("L1 I {}"), "{}");
+    }
+
+    #[test]
+    fn test_fold_literals() {
+        assert_eq!(simplify("{d7, d8, d9, d10} / {d7}"), "{d8, d9, d10}");
+        assert_eq!(simplify("{d1, d2} I {d2, d3}"), "{d2}");
+        assert_eq!(simplify("{d1} / {d1}"), "{}");
+    }
+
+    #[test]
+    fn test_remove_duplicates() {
+        assert_eq!(simplify("L1 U L2 U L1"), "L1 U L2");
+        assert_eq!(simplify("L2 I L2"), "L2");
+        assert_eq!(simplify("(L1 U L2) / (L1 U L2)"), "{}");
+    }
+
+    #[test]
+    fn test_move_generated_out_of_kill() {
+        assert_eq!(simplify("(L1 U {d4}) / {d2}"), "L1 / {d2} U {d4}");
+        assert_eq!(simplify("(L1 U {d2, d4}) / {d2}"), "L1 / {d2} U {d4}");
+        assert_eq!(simplify("(L2 U {d2}) / {d2}"), "L2 / {d2}");
+    }
+
+    #[test]
+    fn test_drop_regenerated_kill() {
+        assert_eq!(simplify("L2 / {d2} U {d2}"), "L2 U {d2}");
+        assert_eq!(simplify("L2 / {d2, d3} U {d3, d4}"), "L2 / {d2} U {d3, d4}");
+    }
+
+    #[test]
+    fn test_merge_kills() {
+        assert_eq!(simplify("L2 / {d3} / {d2}"), "L2 / {d2, d3}");
+        assert_eq!(simplify("L2 / {d3} / L1"), "L2 / {d3} / L1");
+    }
+
+    #[test]
+    fn test_example_keeps_its_solution() {
+        let text = std::fs::read_to_string("oppg.txt").unwrap();
+        let spec = crate::parse_spec(&text).unwrap();
+        let simplified = self::spec(spec.ast());
+        assert_eq!(
+            crate::analysis::reaching_definitions(spec.ast())
+                .unwrap()
+                .history,
+            crate::analysis::reaching_definitions(&simplified)
+                .unwrap()
+                .history
+        );
+        assert_eq!(
+            simplified.equations[13].rhs.to_string(),
+            "L13 / {d4, d5, d6} U {d8, d9, d10}"
+        );
+        assert_eq!(simplified.equations[21].rhs.to_string(), "L21 U {d11}");
+    }
+}
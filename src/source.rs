@@ -0,0 +1,256 @@
+//! Source files and the `include` directive.
+//!
+//! A specification may pull in other files with `include "path";`. Paths are
+//! resolved relative to the directory of the including file, or to the
+//! current directory for text without a path such as standard input. Every
+//! file read is added to a [`SourceMap`], and spans carry the [`FileId`] of
+//! the file they point into so that diagnostics quote the right source.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::diagnostic::Diagnostic;
+use crate::lexer::Lexer;
+use crate::parser::{Node, NodeType, Parser};
+use crate::span::FileId;
+
+/// A file taking part in a specification.
+#[derive(Debug, Clone)]
+pub struct SourceFile {
+    /// How the file is named in diagnostics.
+    pub name: String,
+    /// Where the file was read from, if anywhere. Includes are resolved
+    /// relative to its directory.
+    pub path: Option<PathBuf>,
+    /// The contents of the file.
+    pub text: String,
+}
+
+/// Every file of a specification, indexed by [`FileId`].
+#[derive(Debug, Clone, Default)]
+pub struct SourceMap {
+    files: Vec<SourceFile>,
+}
+
+impl SourceMap {
+    /// A map without files.
+    pub fn new() -> SourceMap {
+        SourceMap { files: Vec::new() }
+    }
+
+    /// Adds a file and returns its ID.
+    pub fn add(
+        &mut self,
+        name: impl Into<String>,
+        path: Option<PathBuf>,
+        text: impl Into<String>,
+    ) -> FileId {
+        self.files.push(SourceFile {
+            name: name.into(),
+            path,
+            text: text.into(),
+        });
+        self.files.len() - 1
+    }
+
+    /// The file with the ID `file`.
+    pub fn get(&self, file: FileId) -> &SourceFile {
+        &self.files[file]
+    }
+
+    /// Every file, in the order they were added.
+    pub fn files(&self) -> &[SourceFile] {
+        &self.files
+    }
+}
+
+/// Lexes and parses `file` and, recursively, every file it includes. Each
+/// `include` statement is replaced by the statements of the included file.
+/// A file included a second time is skipped, and an include cycle is an
+/// error.
+pub fn parse_with_includes(sources: &mut SourceMap, file: FileId) -> (Node, Vec<Diagnostic>) {
+    let mut loader = Loader {
+        sources,
+        stack: Vec::new(),
+        seen: Vec::new(),
+        diagnostics: Vec::new(),
+    };
+    if let Some(path) = loader.sources.get(file).path.clone() {
+        if let Ok(canonical) = fs::canonicalize(path) {
+            loader.seen.push(canonical.clone());
+            loader.stack.push((canonical, file));
+        }
+    }
+    let statements = loader.load(file);
+    let root = Node {
+        node_type: NodeType::Root,
+        children: vec![Node {
+            node_type: NodeType::DataFlowEquationList,
+            children: statements,
+            token: None,
+        }],
+        token: None,
+    };
+    (root, loader.diagnostics)
+}
+
+struct Loader<'a> {
+    sources: &'a mut SourceMap,
+    // The chain of files currently being included, outermost first.
+    stack: Vec<(PathBuf, FileId)>,
+    seen: Vec<PathBuf>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Loader<'_> {
+    fn load(&mut self, file: FileId) -> Vec<Node> {
+        let mut lexer = Lexer::in_file(self.sources.get(file).text.clone(), file);
+        let mut parser = Parser::new(lexer.lex_all());
+        let root = parser.parse();
+        self.diagnostics.extend_from_slice(lexer.diagnostics());
+        self.diagnostics.extend_from_slice(parser.diagnostics());
+
+        let mut statements = Vec::new();
+        for statement in root.children.into_iter().flat_map(|list| list.children) {
+            if statement.node_type == NodeType::Include {
+                statements.extend(self.include(file, &statement));
+            } else {
+                statements.push(statement);
+            }
+        }
+        statements
+    }
+
+    fn include(&mut self, from: FileId, statement: &Node) -> Vec<Node> {
+        let token = statement.token.as_ref().unwrap();
+        let base = self
+            .sources
+            .get(from)
+            .path
+            .as_ref()
+            .and_then(|path| path.parent())
+            .unwrap_or(Path::new(""));
+        let path = base.join(token.string_value());
+        let name = path.display().to_string();
+
+        let (canonical, text) = match fs::canonicalize(&path)
+            .and_then(|canonical| fs::read_to_string(&canonical).map(|text| (canonical, text)))
+        {
+            Ok(found) => found,
+            Err(error) => {
+                self.diagnostics.push(
+                    Diagnostic::error(format!("cannot read `{}`: {}", name, error))
+                        .with_label(token.span, "included here"),
+                );
+                return Vec::new();
+            }
+        };
+
+        if let Some(position) = self.stack.iter().position(|(path, _)| *path == canonical) {
+            let chain = self.stack[position..]
+                .iter()
+                .map(|(_, file)| self.sources.get(*file).name.as_str())
+                .chain([name.as_str()])
+                .collect::<Vec<_>>()
+                .join(" -> ");
+            self.diagnostics.push(
+                Diagnostic::error(format!("include cycle: {}", chain))
+                    .with_label(token.span, "cyclic include"),
+            );
+            return Vec::new();
+        }
+        if self.seen.contains(&canonical) {
+            return Vec::new();
+        }
+
+        let file = self.sources.add(name, Some(path), text);
+        self.seen.push(canonical.clone());
+        self.stack.push((canonical, file));
+        let statements = self.load(file);
+        self.stack.pop();
+        statements
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Writes `files` into a fresh directory under the system temp directory.
+    fn write_files(test: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("lattis-{}-{}", test, std::process::id()));
+        for (name, text) in files {
+            let path = dir.join(name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, text).unwrap();
+        }
+        dir
+    }
+
+    fn load(path: PathBuf) -> (SourceMap, Node, Vec<Diagnostic>) {
+        let mut sources = SourceMap::new();
+        let text = fs::read_to_string(&path).unwrap();
+        let file = sources.add(path.display().to_string(), Some(path), text);
+        let (root, diagnostics) = parse_with_includes(&mut sources, file);
+        (sources, root, diagnostics)
+    }
+
+    #[test]
+    fn test_include_relative_to_including_file() {
+        let dir = write_files(
+            "relative",
+            &[
+                ("main.txt", "include \"procs/p.txt\";\nL3 = L2;"),
+                ("procs/p.txt", "L1 = {d1};\ninclude \"q.txt\";"),
+                ("procs/q.txt", "L2 = L1;"),
+            ],
+        );
+        let (sources, root, diagnostics) = load(dir.join("main.txt"));
+        assert!(diagnostics.is_empty());
+        let equations = &root[0];
+        assert_eq!(equations.len(), 3);
+        assert_eq!(sources.files().len(), 3);
+        let l2 = equations[1][0].token.as_ref().unwrap();
+        assert_eq!(l2.lexeme, "L2");
+        assert_eq!(
+            sources.get(l2.span.file).name,
+            dir.join("procs/q.txt").display().to_string()
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_include_cycle() {
+        let dir = write_files(
+            "cycle",
+            &[
+                ("a.txt", "include \"b.txt\";\nL1 = {};"),
+                ("b.txt", "L2 = L1;\ninclude \"a.txt\";"),
+            ],
+        );
+        let (sources, root, diagnostics) = load(dir.join("a.txt"));
+        assert_eq!(root[0].len(), 2);
+        assert_eq!(diagnostics.len(), 1);
+        let a = dir.join("a.txt").display().to_string();
+        let b = dir.join("b.txt").display().to_string();
+        assert_eq!(
+            diagnostics[0].message,
+            format!("include cycle: {} -> {} -> {}", a, b, a)
+        );
+        let span = diagnostics[0].span.unwrap();
+        assert_eq!(sources.get(span.file).name, b);
+        assert_eq!(span.line, 2);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_include_missing_file() {
+        let dir = write_files("missing", &[("a.txt", "L1 = {};\ninclude \"nope.txt\";")]);
+        let (_, _, diagnostics) = load(dir.join("a.txt"));
+        assert!(diagnostics[0].message.starts_with("cannot read `"));
+        assert_eq!(diagnostics[0].label.as_deref(), Some("included here"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,49 @@
+//! Positions in source text.
+
+/// Identifies a file in a [`SourceMap`](crate::source::SourceMap).
+pub type FileId = usize;
+
+/// A region of source text.
+///
+/// `start` and `end` are byte offsets into the file `file`; `line` and
+/// `column` are 1-based and point at `start`, with the column counted in
+/// characters.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub struct Span {
+    /// Byte offset of the first byte.
+    pub start: usize,
+    /// Byte offset just past the last byte.
+    pub end: usize,
+    /// Line of `start`, from 1.
+    pub line: usize,
+    /// Column of `start` in characters, from 1.
+    pub column: usize,
+    /// The file the span is in.
+    pub file: FileId,
+}
+
+impl Span {
+    /// Length in bytes.
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    /// Whether the span covers no text.
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    /// The smallest span covering both `self` and `other`.
+    pub fn to(&self, other: Span) -> Span {
+        if other.start < self.start {
+            return other.to(*self);
+        }
+        Span {
+            start: self.start,
+            end: self.end.max(other.end),
+            line: self.line,
+            column: self.column,
+            file: self.file,
+        }
+    }
+}
@@ -0,0 +1,197 @@
+//! Writes a typed syntax tree back as specification text.
+//!
+//! The output parses back into the same tree: operators get only the
+//! parentheses their precedence requires, and every statement ends in `;` on
+//! its own line. Statements are written universe first, then `let` bindings,
+//! then equations in their original order.
+
+use std::fmt;
+
+use crate::ast::{Element, Equation, Expr, LetBinding, SetLit, Spec, Universe};
+
+/// The symbols operators and the empty set are written with.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Notation {
+    /// `U`, `I`, `/` and `{}`.
+    Ascii,
+    /// `∪`, `∩`, `∖` and `∅`.
+    Unicode,
+}
+
+struct Symbols {
+    union: &'static str,
+    intersection: &'static str,
+    difference: &'static str,
+    empty: &'static str,
+}
+
+fn symbols(notation: Notation) -> Symbols {
+    match notation {
+        Notation::Ascii => Symbols {
+            union: "U",
+            intersection: "I",
+            difference: "/",
+            empty: "{}",
+        },
+        Notation::Unicode => Symbols {
+            union: "∪",
+            intersection: "∩",
+            difference: "∖",
+            empty: "∅",
+        },
+    }
+}
+
+/// The whole specification, one statement per line.
+pub fn spec(spec: &Spec, notation: Notation) -> String {
+    let mut out = String::new();
+    for Universe { value, .. } in &spec.universes {
+        out.push_str(&format!("universe {};\n", expr(value, notation)));
+    }
+    for LetBinding { name, value } in &spec.bindings {
+        out.push_str(&format!("let {} = {};\n", name.name, expr(value, notation)));
+    }
+    for equation in &spec.equations {
+        out.push_str(&self::equation(equation, notation));
+        out.push('\n');
+    }
+    out
+}
+
+/// `lhs = rhs;`
+pub fn equation(equation: &Equation, notation: Notation) -> String {
+    format!("{} = {};", equation.lhs.name, expr(&equation.rhs, notation))
+}
+
+/// A single expression, parenthesised only where precedence requires it.
+pub fn expr(expr: &Expr, notation: Notation) -> String {
+    let symbols = symbols(notation);
+    let mut out = String::new();
+    write_expr(&mut out, expr, &symbols);
+    out
+}
+
+// Mirrors the parser: intersection and difference bind tighter than union.
+fn binding_power(expr: &Expr) -> u8 {
+    match expr {
+        Expr::Union { .. } => 1,
+        Expr::Intersection { .. } | Expr::Diff { .. } => 2,
+        _ => u8::MAX,
+    }
+}
+
+fn write_expr(out: &mut String, expr: &Expr, symbols: &Symbols) {
+    let operand = |out: &mut String, operand: &Expr, needs_parentheses: bool| {
+        if needs_parentheses {
+            out.push('(');
+            write_expr(out, operand, symbols);
+            out.push(')');
+        } else {
+            write_expr(out, operand, symbols);
+        }
+    };
+
+    let (lhs, operator, rhs) = match expr {
+        Expr::Var(ident) => return out.push_str(&ident.name),
+        Expr::SetLit(set) => return write_set(out, set, symbols),
+        Expr::Complement { operand: inner, .. } => {
+            out.push('~');
+            return operand(out, inner, binding_power(inner) < u8::MAX);
+        }
+        Expr::Union { lhs, rhs, .. } => (lhs, symbols.union, rhs),
+        Expr::Intersection { lhs, rhs, .. } => (lhs, symbols.intersection, rhs),
+        Expr::Diff { lhs, rhs, .. } => (lhs, symbols.difference, rhs),
+    };
+    // Operators are left associative, so a right operand of the same
+    // precedence needs parentheses too.
+    let power = binding_power(expr);
+    operand(out, lhs, binding_power(lhs) < power);
+    out.push_str(&format!(" {} ", operator));
+    operand(out, rhs, binding_power(rhs) <= power);
+}
+
+fn write_set(out: &mut String, set: &SetLit, symbols: &Symbols) {
+    if set.elements.is_empty() {
+        return out.push_str(symbols.empty);
+    }
+    let elements = set
+        .elements
+        .iter()
+        .map(|element| match element {
+            Element::Definition(definition) => definition.name.clone(),
+            Element::Range { start, end } => format!("{}..{}", start.name, end.name),
+        })
+        .collect::<Vec<_>>();
+    out.push_str(&format!("{{{}}}", elements.join(", ")));
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", expr(self, Notation::Ascii))
+    }
+}
+
+impl fmt::Display for Spec {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", spec(self, Notation::Ascii))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::Ident;
+    use crate::span::Span;
+
+    fn parse(text: &str) -> Spec {
+        crate::parse_spec(text).unwrap().ast().clone()
+    }
+
+    fn var(name: &str) -> Box<Expr> {
+        Box::new(Expr::Var(Ident {
+            name: name.to_string(),
+            span: Span::default(),
+        }))
+    }
+
+    #[test]
+    fn test_unparse_round_trip() {
+        let text = "universe {d1, d2, d3};\nlet K = {d1};\nL1 = ~(L2 U K) / ~L2;\nL2 = (L1 U L2) / L1 U L1 I (L2 / K) U (L1 U ∅);\n";
+        let spec = parse(text);
+        let unparsed = spec.to_string();
+        assert_eq!(
+            unparsed,
+            "universe {d1, d2, d3};\nlet K = {d1};\nL1 = ~(L2 U K) / ~L2;\nL2 = (L1 U L2) / L1 U L1 I (L2 / K) U (L1 U {});\n"
+        );
+        assert_eq!(parse(&unparsed).to_string(), unparsed);
+    }
+
+    #[test]
+    fn test_unparse_generated_tree() {
+        // (L1 / L2) / (L3 / L4), built without a parser.
+        let expr = Expr::Diff {
+            lhs: Box::new(Expr::Diff {
+                lhs: var("L1"),
+                rhs: var("L2"),
+                op: Span::default(),
+            }),
+            rhs: Box::new(Expr::Diff {
+                lhs: var("L3"),
+                rhs: var("L4"),
+                op: Span::default(),
+            }),
+            op: Span::default(),
+        };
+        assert_eq!(expr.to_string(), "L1 / L2 / (L3 / L4)");
+        assert_eq!(
+            super::expr(
+                &Expr::Complement {
+                    operand: Box::new(expr),
+                    op: Span::default()
+                },
+                Notation::Unicode
+            ),
+            "~(L1 ∖ L2 ∖ (L3 ∖ L4))"
+        );
+    }
+}